@@ -0,0 +1,304 @@
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::item::Item;
+use crate::list::List;
+use crate::storage::Storage;
+
+// where the ruby version of boom keeps its data
+pub const BOOM_FILE: &str = "/.boom";
+const ALIASES_LIST: &str = "aliases";
+// bookmarks that aren't in any folder
+const BOOKMARKS_LIST: &str = "bookmarks";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Boom,
+    Aliases,
+    Bookmarks,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "boom" => Ok(Format::Boom),
+            "aliases" => Ok(Format::Aliases),
+            "bookmarks" => Ok(Format::Bookmarks),
+            _ => Err(anyhow!("format should be boom, aliases, or bookmarks")),
+        }
+    }
+}
+
+pub fn import(format: Format, data: &str) -> Result<Vec<List>> {
+    match format {
+        Format::Boom => Storage::parse(data),
+        Format::Aliases => Ok(parse_aliases(data)),
+        Format::Bookmarks => Ok(parse_bookmarks(data)),
+    }
+}
+
+pub fn export(format: Format, lists: &[List]) -> Result<String> {
+    match format {
+        Format::Boom => export_boom(lists),
+        Format::Aliases => Ok(export_aliases(lists)),
+        Format::Bookmarks => Ok(export_bookmarks(lists)),
+    }
+}
+
+// plain name/value pairs only, so the ruby version can still read it
+fn export_boom(lists: &[List]) -> Result<String> {
+    let plain = lists.iter().map(|list| {
+        let mut out = List::new(list.name.clone());
+        for item in &list.items {
+            out.add_item(Item::new(item.name.clone(), item.value.clone()));
+        }
+        out.to_hash()
+    }).collect::<Vec<_>>();
+    Ok(serde_json::to_string_pretty(&HashMap::from([("lists", plain)]))?)
+}
+
+/// alias x='...' lines become items in the aliases list. anything else is skipped.
+fn parse_aliases(data: &str) -> Vec<List> {
+    let mut list = List::new(ALIASES_LIST.to_string());
+    for line in data.lines() {
+        let Some(rest) = line.trim().strip_prefix("alias ") else {
+            continue;
+        };
+        let Some((name, value)) = rest.trim().split_once('=') else {
+            continue;
+        };
+        if name.is_empty() || name.starts_with('-') {
+            continue;
+        }
+        list.add_item(Item::new(name.to_string(), unquote(value.trim())));
+    }
+    if list.items.is_empty() { Vec::new() } else { vec![list] }
+}
+
+fn unquote(value: &str) -> String {
+    if let Some(inner) = value.strip_prefix('\'') {
+        // '\'' is how a single quote gets into a single quoted string
+        let inner = inner.replace("'\\''", "\u{0}");
+        let end = inner.find('\'').unwrap_or(inner.len());
+        return inner[..end].replace('\u{0}', "'");
+    }
+    if let Some(inner) = value.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => break,
+                _ => out.push(c),
+            }
+        }
+        return out;
+    }
+    value.split_whitespace().next().unwrap_or("").to_string()
+}
+
+fn export_aliases(lists: &[List]) -> String {
+    let mut out = String::new();
+    if let Some(list) = lists.iter().find(|l| l.name == ALIASES_LIST) {
+        for item in &list.items {
+            out.push_str(&format!("alias {}='{}'\n", item.name, item.value.replace('\'', "'\\''")));
+        }
+    }
+    out
+}
+
+/// netscape bookmark html, which every browser exports. folders become lists,
+/// nested folders are flattened to their own name.
+fn parse_bookmarks(data: &str) -> Vec<List> {
+    let mut lists: Vec<List> = Vec::new();
+    // the folder each open <DL> belongs to, None for the top level
+    let mut folders: Vec<Option<String>> = Vec::new();
+    let mut pending: Option<String> = None;
+    let mut rest = data;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let end = rest.find('>').map(|e| e + 1).unwrap_or(rest.len());
+        let tag = &rest[..end];
+        let is = |name: &str| tag.len() >= name.len() && tag.as_bytes()[..name.len()].eq_ignore_ascii_case(name.as_bytes());
+        if is("<H3") {
+            let (text, after) = inner_text(&rest[end..], "</H3");
+            pending = Some(text);
+            rest = after;
+            continue;
+        }
+        if is("<DL") {
+            folders.push(pending.take());
+        } else if is("</DL") {
+            folders.pop();
+        } else if is("<A ") {
+            let href = attr(tag, "HREF");
+            let (text, after) = inner_text(&rest[end..], "</A");
+            if let Some(href) = href {
+                let folder = folders.last().cloned().flatten().unwrap_or(BOOKMARKS_LIST.to_string());
+                let name = if text.is_empty() { href.clone() } else { text };
+                match lists.iter_mut().find(|l| l.name == folder) {
+                    Some(list) => list.add_item(Item::new(name, href)),
+                    None => {
+                        let mut list = List::new(folder);
+                        list.add_item(Item::new(name, href));
+                        lists.push(list);
+                    },
+                }
+            }
+            rest = after;
+            continue;
+        }
+        rest = &rest[end..];
+    }
+    lists
+}
+
+// where needle (ascii) first turns up in data, whatever the case. positions are
+// data's own, which uppercasing a copy wouldn't keep for non-ascii text
+fn find_ignore_case(data: &str, needle: &str) -> Option<usize> {
+    data.as_bytes().windows(needle.len()).position(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+// text up to the closing tag, and whatever comes after it
+fn inner_text<'a>(data: &'a str, close: &str) -> (String, &'a str) {
+    let end = find_ignore_case(data, close).unwrap_or(data.len());
+    let after = data[end..].find('>').map(|e| &data[end + e + 1..]).unwrap_or("");
+    (decode(data[..end].trim()), after)
+}
+
+fn attr(tag: &str, name: &str) -> Option<String> {
+    let start = find_ignore_case(tag, &format!("{}=\"", name))? + name.len() + 2;
+    let len = tag[start..].find('"')?;
+    Some(decode(&tag[start..start + len]))
+}
+
+fn decode(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn encode(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn export_bookmarks(lists: &[List]) -> String {
+    let mut out = String::from("<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+        <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+        <TITLE>Bookmarks</TITLE>\n\
+        <H1>Bookmarks</H1>\n\
+        <DL><p>\n");
+    for list in lists {
+        // only things a browser can open
+        let links = list.items.iter()
+            .map(|i| (i, i.url()))
+            .filter(|(_, url)| url.starts_with("http://") || url.starts_with("https://"))
+            .collect::<Vec<_>>();
+        if links.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("    <DT><H3>{}</H3>\n    <DL><p>\n", encode(&list.name)));
+        for (item, url) in links {
+            out.push_str(&format!("        <DT><A HREF=\"{}\">{}</A>\n", encode(&url), encode(&item.name)));
+        }
+        out.push_str("    </DL><p>\n");
+    }
+    out.push_str("</DL><p>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOKMARKS: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1700000000" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><A HREF="https://github.com/" ADD_DATE="1700000000">GitHub</A>
+        <DT><H3>rust</H3>
+        <DL><p>
+            <DT><A HREF="https://doc.rust-lang.org/std/?search=a&amp;b">std &amp; docs</A>
+        </DL><p>
+        <DT><A HREF="https://lobste.rs/">Lobsters</A>
+    </DL><p>
+    <DT><A HREF="https://example.com/"></A>
+</DL><p>
+"#;
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!(Format::Aliases, "aliases".parse().unwrap());
+        assert!("nope".parse::<Format>().is_err());
+    }
+    #[test]
+    fn test_parse_aliases() {
+        let data = "# comment\nalias ll='ls -la'\nalias gs=\"git status\"\n  alias k=kubectl\nalias q='echo '\\''hi'\\'''\nexport FOO=bar\nalias -g G='| grep'\n";
+        let lists = parse_aliases(data);
+        assert_eq!(1, lists.len());
+        let list = &lists[0];
+        assert_eq!(ALIASES_LIST, list.name);
+        assert_eq!(4, list.items.len());
+        assert_eq!("ls -la", list.find_item("ll").unwrap().value);
+        assert_eq!("git status", list.find_item("gs").unwrap().value);
+        assert_eq!("kubectl", list.find_item("k").unwrap().value);
+        assert_eq!("echo 'hi'", list.find_item("q").unwrap().value);
+    }
+    #[test]
+    fn test_aliases_round_trip() {
+        let lists = parse_aliases("alias q='echo '\\''hi'\\'''\nalias ll='ls -la'\n");
+        let exported = export_aliases(&lists);
+        assert_eq!("alias q='echo '\\''hi'\\'''\nalias ll='ls -la'\n", exported);
+        assert_eq!(lists, parse_aliases(&exported));
+    }
+    #[test]
+    fn test_parse_bookmarks() {
+        let lists = parse_bookmarks(BOOKMARKS);
+        let names = lists.iter().map(|l| l.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["Bookmarks bar", "rust", BOOKMARKS_LIST], names);
+        assert_eq!(2, lists[0].items.len());
+        assert_eq!("https://lobste.rs/", lists[0].find_item("Lobsters").unwrap().value);
+        assert_eq!("https://doc.rust-lang.org/std/?search=a&b", lists[1].find_item("std & docs").unwrap().value);
+        assert_eq!("https://example.com/", lists[2].items[0].name);
+    }
+    #[test]
+    fn test_parse_bookmarks_non_ascii() {
+        // uppercasing ı makes it longer, which mustn't move where tags are
+        let data = "<DL><p>\n<DT><H3>ııı</H3>\n<DL><p>\n\
+            <DT><a href=\"https://example.com/ı\">ıııı</a>\n\
+            <DT><A HREF=\"https://example.com/ß\">straße ﬁ</A>\n</DL><p>\n</DL><p>\n";
+        let lists = parse_bookmarks(data);
+        assert_eq!("ııı", lists[0].name);
+        assert_eq!("https://example.com/ı", lists[0].find_item("ıııı").unwrap().value);
+        assert_eq!("https://example.com/ß", lists[0].find_item("straße ﬁ").unwrap().value);
+    }
+    #[test]
+    fn test_bookmarks_round_trip() {
+        let mut lists = parse_bookmarks(BOOKMARKS);
+        lists[0].add_item(Item::new("not a link".to_string(), "ls -la".to_string()));
+        let exported = export_bookmarks(&lists);
+        assert!(!exported.contains("not a link"));
+        lists[0].delete_item("not a link");
+        assert_eq!(lists, parse_bookmarks(&exported));
+    }
+    #[test]
+    fn test_boom_export_is_plain() {
+        let mut list = List::new("urls".to_string());
+        list.set_item("github", "https://github.com");
+        list.items[0].add_tag("code");
+        let exported = export_boom(&[list]).unwrap();
+        assert_eq!(r#"{"lists":[{"urls":[{"github":"https://github.com"}]}]}"#, serde_json::to_string(&serde_json::from_str::<serde_json::Value>(&exported).unwrap()).unwrap());
+        let lists = import(Format::Boom, &exported).unwrap();
+        assert_eq!("https://github.com", lists[0].items[0].value);
+    }
+}
@@ -0,0 +1,102 @@
+const MATCH: i64 = 16;
+const CONSECUTIVE: i64 = 24;
+const WORD_START: i64 = 20;
+const FIRST_CHAR: i64 = 12;
+
+/// scores how well `query` fuzzy matches `candidate`, returning None if it
+/// doesn't match at all. every character of the query has to show up in the
+/// candidate in order (case insensitive). runs of consecutive characters and
+/// matches at the start of words score higher, while gaps and leftover
+/// characters in the candidate cost a little.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query.to_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let lower: Vec<char> = candidate.to_lowercase().chars().collect();
+    if query.is_empty() || lower.len() != chars.len() {
+        return None;
+    }
+    // greedy from every place the first query character shows up, keep the best
+    lower.iter()
+        .enumerate()
+        .filter(|(_, c)| **c == query[0])
+        .filter_map(|(start, _)| score_from(&query, &chars, &lower, start))
+        .max()
+}
+
+fn score_from(query: &[char], chars: &[char], lower: &[char], start: usize) -> Option<i64> {
+    let mut total = 0;
+    let mut matched = 0;
+    let mut prev: Option<usize> = None;
+    let mut qi = 0;
+    for (i, c) in lower.iter().enumerate().skip(start) {
+        if qi == query.len() {
+            break;
+        }
+        if *c != query[qi] {
+            continue;
+        }
+        total += MATCH;
+        if i == 0 {
+            total += FIRST_CHAR;
+        }
+        if is_word_start(chars, i) {
+            total += WORD_START;
+        }
+        match prev {
+            Some(p) if p + 1 == i => total += CONSECUTIVE,
+            Some(p) => total -= (i - p - 1) as i64,
+            None => {},
+        }
+        prev = Some(i);
+        matched += 1;
+        qi += 1;
+    }
+    if qi < query.len() {
+        return None;
+    }
+    // leftover characters mean a looser match, so "ssh" beats "ssh prod"
+    Some(total - (chars.len() - matched) as i64)
+}
+
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    if i == 0 {
+        return true;
+    }
+    let prev = chars[i - 1];
+    !prev.is_alphanumeric() || (prev.is_lowercase() && chars[i].is_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_no_match() {
+        assert!(score("xyz", "github").is_none());
+        assert!(score("bug", "github").is_none());
+        assert!(score("", "github").is_none());
+    }
+    #[test]
+    fn test_score_subsequence() {
+        assert!(score("ghb", "github").is_some());
+        assert!(score("GitHub", "github").is_some());
+    }
+    #[test]
+    fn test_score_exact_beats_partial() {
+        assert!(score("ssh", "ssh").unwrap() > score("ssh", "ssh prod").unwrap());
+    }
+    #[test]
+    fn test_score_consecutive_beats_scattered() {
+        assert!(score("git", "github").unwrap() > score("git", "good night").unwrap());
+    }
+    #[test]
+    fn test_score_word_start() {
+        assert!(score("dp", "deploy prod").unwrap() > score("dp", "adept").unwrap());
+        assert!(score("gh", "getHome").unwrap() > score("gh", "aught").unwrap());
+    }
+    #[test]
+    fn test_score_ignores_query_spaces() {
+        assert!(score("ssh prod", "ssh prod").is_some());
+        assert!(score("ssh prod", "sshprod").is_some());
+    }
+}
@@ -2,21 +2,87 @@ use super::{
     HashMap,
     Deserialize,
     Serialize,
+    Result,
 };
+use crate::template;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// everything past name and value is optional, so plain boom items still load
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Item {
+    #[serde(default)]
     pub name: String,
     pub value: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub uses: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
 }
 
 impl Item {
     pub fn new(name: String, value: String) -> Self {
-        Item { name, value }
+        Item { name, value, ..Default::default() }
+    }
+    /// builds an item from the stored json, either a plain boom string or an object
+    /// with the value and whatever metadata we've picked up. anything else is an
+    /// error rather than dropped, since the next save would lose it for good.
+    pub fn from_value(name: &str, value: &Value) -> Result<Self> {
+        match value {
+            Value::String(v) => Ok(Item::new(name.to_string(), v.clone())),
+            Value::Object(_) => {
+                let mut item: Item = serde_json::from_value(value.clone())?;
+                item.name = name.to_string();
+                Ok(item)
+            },
+            _ => Err(anyhow!("expected a string or an object, got {}", value)),
+        }
+    }
+    /// the stored json for the item. items without metadata stay plain strings.
+    pub fn to_value(&self) -> Value {
+        if *self == Item::new(self.name.clone(), self.value.clone()) {
+            return Value::String(self.value.clone());
+        }
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(obj) = value.as_object_mut() {
+            obj.remove("name");
+        }
+        value
+    }
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+    pub fn add_tag(&mut self, tag: &str) {
+        if !self.has_tag(tag) {
+            self.tags.push(tag.to_string());
+            self.updated_at = Some(Utc::now());
+        }
+    }
+    pub fn mark_used(&mut self) {
+        self.last_used = Some(Utc::now());
+        self.uses += 1;
+    }
+    /// the last time anything happened to the item.
+    pub fn last_active(&self) -> Option<DateTime<Utc>> {
+        self.last_used.max(self.updated_at).max(self.created_at)
     }
     pub fn short_name(&self) -> String {
-        if self.name.len() > 15 { 
-            format!("{}...", &self.name[..15])
+        // 15 chars, a byte slice can land inside one
+        if self.name.chars().count() > 15 { 
+            format!("{}...", self.name.chars().take(15).collect::<String>())
         } else { 
             self.name.clone()
         }
@@ -27,9 +93,16 @@ impl Item {
             .unwrap_or(&self.value)
             .to_string()
     }
-    pub fn to_hash(&self) -> HashMap<String, String> {
+    pub fn is_template(&self) -> bool {
+        template::has_placeholders(&self.value)
+    }
+    /// the value with any {{...}} placeholders filled in from args.
+    pub fn expanded(&self, args: &[String]) -> Result<String> {
+        template::expand(&self.value, args)
+    }
+    pub fn to_hash(&self) -> HashMap<String, Value> {
         let mut map = HashMap::new();
-        map.insert(self.name.clone(), self.value.clone());
+        map.insert(self.name.clone(), self.to_value());
         map
     }
 }
@@ -50,11 +123,43 @@ mod tests {
         assert_eq!(item.short_name(), "this is a very ...");
         let short_item = Item::new("short".to_string(), "value".to_string());
         assert_eq!(short_item.short_name(), "short");
+        let item = Item::new("münchner straße ist lang".to_string(), "value".to_string());
+        assert_eq!(item.short_name(), "münchner straße...");
+    }
+    #[test]
+    fn test_item_expanded() {
+        let item = Item::new("prod".to_string(), "ssh deploy@{{1}}".to_string());
+        assert!(item.is_template());
+        assert_eq!("ssh deploy@web3", item.expanded(&["web3".to_string()]).unwrap());
+        assert_eq!("ssh deploy@{{1}}", item.value);
     }
     #[test]
     fn test_item_to_hash() {
         let item = Item::new("foo".to_string(), "bar".to_string());
-        let expected = HashMap::from([("foo".to_string(), "bar".to_string())]);
+        let expected = HashMap::from([("foo".to_string(), Value::String("bar".to_string()))]);
         assert_eq!(expected, item.to_hash());
     }
+    #[test]
+    fn test_item_value_round_trip() {
+        let mut item = Item::new("foo".to_string(), "bar".to_string());
+        item.add_tag("work");
+        item.add_tag("work");
+        item.description = Some("the foo".to_string());
+        item.mark_used();
+        let value = item.to_value();
+        assert!(value.get("name").is_none());
+        assert_eq!(item, Item::from_value("foo", &value).unwrap());
+        assert_eq!(vec!["work".to_string()], item.tags);
+        assert_eq!(1, item.uses);
+        assert_eq!(item.last_used, item.last_active());
+    }
+    #[test]
+    fn test_item_from_value_plain() {
+        let item = Item::from_value("foo", &Value::String("bar".to_string())).unwrap();
+        assert_eq!(Item::new("foo".to_string(), "bar".to_string()), item);
+        assert!(Item::from_value("foo", &Value::Null).is_err());
+        assert!(Item::from_value("foo", &serde_json::json!(42)).is_err());
+        let err = Item::from_value("foo", &serde_json::json!({"tags": ["work"]})).unwrap_err();
+        assert!(err.to_string().contains("missing field `value`"), "{}", err);
+    }
 }
\ No newline at end of file
@@ -1,81 +1,390 @@
 use anyhow::{Result, anyhow};
+use rand::seq::SliceRandom;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{BufReader, ErrorKind};
+use std::fs;
+use std::io::{self, BufRead, Write};
 
-mod item;
-mod list;
-mod storage;
+pub mod formats;
+mod fuzzy;
+pub mod item;
+pub mod list;
+pub mod storage;
+pub mod sync;
+mod template;
 
+use item::Item;
+use list::List;
+use storage::{Hit, SortBy, Storage};
+use sync::Sync;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DB {
-    file: String,
-    data: HashMap<String, Vec<String>>
+pub const USAGE: &str = r"Usage:
+bmr [--sort size|recent|usage]        overview of all lists, biggest first by default
+bmr <list>                            show the items in a list (creates it if missing)
+bmr <name> [args...]                  print the value of an item, filling in any {{1}} placeholders from args
+bmr <list> <name> [args...]           print the value of an item in a list, same as above
+bmr <list> <name> <value>             add or overwrite an item in a list (if it isn't a template)
+bmr echo  [list] <name>               print the raw value of an item, placeholders and all
+bmr random [list]                     print a random item, from one list or from all of them
+bmr find  <query> [--pick]            fuzzy find across lists, item names, and values
+bmr list  [--tag <tag>]               every item, or just the ones with a tag
+bmr tag   <name> <tag>...             tag an item
+bmr describe <name> <description>     add a description to an item
+bmr recent [n]                        the n most recently used or changed items (10 by default)
+bmr import boom|aliases|bookmarks <file>   import from ruby boom (~/.boom by default), alias lines, or bookmark html
+bmr export boom|aliases|bookmarks [file]   export to the same formats, stdout if no file is given
+bmr sync  init <repo-path>            set up syncing through a git repo (a bare repo works fine)
+bmr sync  push                        merge in remote changes and push, refuses if anything conflicts
+bmr sync  pull                        merge in remote changes, conflicts keep the local version
+
+placeholders: {{1}}, {{2}}, ... extra args, {{env:NAME}} env vars, {{date}} or {{date:%Y%m%d}} today
+";
+
+pub struct Boomr {
+    storage: Storage,
+    // where --pick reads the choice from
+    input: Box<dyn BufRead>,
 }
 
-impl DB {
-    pub fn connect(file: String) -> Result<Self> {
-        let f = match File::open(&file) {
-            Ok(file) => file,
-            Err(e) if e.kind() == ErrorKind::NotFound => {
-                File::create(&file).map_err(|e| anyhow!("failed to create db: {}", e))?;
-                return Ok(DB { file, data: HashMap::new() });
+impl Boomr {
+    pub fn new(storage: Storage) -> Self {
+        Boomr{ storage, input: Box::new(io::BufReader::new(io::stdin())) }
+    }
+    /// read picks from somewhere other than stdin, mostly for tests.
+    pub fn input(mut self, input: Box<dyn BufRead>) -> Self {
+        self.input = input;
+        self
+    }
+    pub fn run(&mut self, output: &mut dyn Write, args: Vec<String>) -> Result<()> {
+        if args.is_empty() {
+            return self.overview(output);
+        }
+        match args[0].as_str() {
+            "help" => writeln!(output, "{USAGE}")?,
+            "--sort" => {
+                let by = args.get(1).ok_or_else(|| anyhow!("sort by size, recent, or usage"))?.parse()?;
+                self.overview_by(output, by)?;
+            },
+            "echo" => {
+                let found = match args.len() {
+                    2 => self.storage.find_item(&args[1]),
+                    3 => self.storage.find_list(&args[1]).and_then(|l| l.find_item(&args[2]).map(|i| (l, i))),
+                    _ => {
+                        eprintln!("{USAGE}");
+                        return Err(anyhow!("echo takes a name or a list and a name"));
+                    },
+                };
+                let (list, item) = found.ok_or_else(|| anyhow!("'{}' not found", args[args.len() - 1]))?;
+                writeln!(output, "{}", item.value)?;
+                let (list, item) = (list.name.clone(), item.name.clone());
+                self.storage.mark_used(&list, &item)?;
+            },
+            "random" => {
+                let items = match args.get(1) {
+                    Some(name) => {
+                        let list = self.storage.find_list(name).ok_or_else(|| anyhow!("list '{}' not found", name))?;
+                        list.items.iter().map(|i| (list, i)).collect()
+                    },
+                    None => self.storage.all_lists().iter()
+                        .flat_map(|l| l.items.iter().map(move |i| (l, i)))
+                        .collect::<Vec<_>>(),
+                };
+                let (list, item) = items.choose(&mut rand::thread_rng()).ok_or_else(|| anyhow!("no items to pick from"))?;
+                writeln!(output, "  {}: {}", item.short_name(), item.value)?;
+                let (list, item) = (list.name.clone(), item.name.clone());
+                self.storage.mark_used(&list, &item)?;
+            },
+            "list" => {
+                let items = match (args.get(1).map(|a| a.as_str()), args.get(2)) {
+                    (None, _) => self.storage.all_lists().iter()
+                        .flat_map(|l| l.items.iter().map(move |i| (l, i)))
+                        .collect(),
+                    (Some("--tag"), Some(tag)) => self.storage.tagged(tag),
+                    _ => {
+                        eprintln!("{USAGE}");
+                        return Err(anyhow!("list takes an optional --tag <tag>"));
+                    },
+                };
+                for (list, item) in items {
+                    writeln!(output, "{}", Self::format_item(list, item))?;
+                }
+            },
+            "tag" => {
+                if args.len() < 3 {
+                    eprintln!("{USAGE}");
+                    return Err(anyhow!("need an item and at least one tag"));
+                }
+                let item = self.storage.find_item_mut(&args[1]).ok_or_else(|| anyhow!("'{}' not found", args[1]))?;
+                for tag in &args[2..] {
+                    item.add_tag(tag);
+                }
+                writeln!(output, "boomr! '{}' is tagged {}", item.name, item.tags.join(", "))?;
+                self.storage.save()?;
+            },
+            "describe" => {
+                if args.len() < 3 {
+                    eprintln!("{USAGE}");
+                    return Err(anyhow!("need an item and a description"));
+                }
+                let item = self.storage.find_item_mut(&args[1]).ok_or_else(|| anyhow!("'{}' not found", args[1]))?;
+                item.description = Some(args[2..].join(" "));
+                item.updated_at = Some(chrono::Utc::now());
+                writeln!(output, "boomr! described '{}'", item.name)?;
+                self.storage.save()?;
+            },
+            "recent" => {
+                let n = match args.get(1) {
+                    Some(n) => n.parse::<usize>().map_err(|_| anyhow!("recent takes a number"))?,
+                    None => 10,
+                };
+                for (list, item) in self.storage.recent(n) {
+                    let when = item.last_active().map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+                    writeln!(output, "  {}  {}", when, Self::format_item(list, item))?;
+                }
+            },
+            "find" => {
+                let pick = args.iter().any(|a| a == "--pick" || a == "-p");
+                let query = args[1..].iter()
+                    .filter(|a| *a != "--pick" && *a != "-p")
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                if query.is_empty() {
+                    eprintln!("{USAGE}");
+                    return Err(anyhow!("need a query to find"));
+                }
+                self.find(output, &query, pick)?;
+            },
+            "import" => {
+                let format: formats::Format = args.get(1).ok_or_else(|| anyhow!("import needs a format"))?.parse()?;
+                let path = match (args.get(2), format) {
+                    (Some(path), _) => path.clone(),
+                    (None, formats::Format::Boom) => format!("{}{}", std::env::var("HOME").unwrap_or_else(|_| ".".to_string()), formats::BOOM_FILE),
+                    (None, _) => return Err(anyhow!("import needs a file")),
+                };
+                let data = fs::read_to_string(&path).map_err(|e| anyhow!("failed to read {}: {}", path, e))?;
+                let count = self.storage.import_lists(formats::import(format, &data)?);
+                self.storage.save()?;
+                writeln!(output, "boomr! imported {} items from '{}'", count, path)?;
+            },
+            "export" => {
+                let format: formats::Format = args.get(1).ok_or_else(|| anyhow!("export needs a format"))?.parse()?;
+                let data = formats::export(format, self.storage.all_lists())?;
+                match args.get(2) {
+                    Some(path) => {
+                        fs::write(path, data)?;
+                        writeln!(output, "boomr! exported to '{}'", path)?;
+                    },
+                    None => write!(output, "{}", data)?,
+                }
+            },
+            "sync" => {
+                let sync = Sync::new(self.storage.path());
+                match (args.get(1).map(|a| a.as_str()), args.get(2)) {
+                    (Some("init"), Some(repo)) => {
+                        sync.init(repo)?;
+                        writeln!(output, "boomr! syncing through '{}', run `bmr sync pull` or `bmr sync push`", repo)?;
+                    },
+                    (Some("push"), None) => {
+                        sync.push(&mut self.storage)?;
+                        writeln!(output, "boomr! pushed")?;
+                    },
+                    (Some("pull"), None) => {
+                        let conflicts = sync.pull(&mut self.storage)?;
+                        for conflict in &conflicts {
+                            writeln!(output, "  conflict, kept local: {}", conflict)?;
+                        }
+                        writeln!(output, "boomr! pulled ({} conflicts)", conflicts.len())?;
+                    },
+                    _ => {
+                        eprintln!("{USAGE}");
+                        return Err(anyhow!("sync takes init <repo-path>, push, or pull"));
+                    },
+                }
+            },
+            _ => self.boom(output, &args)?,
+        }
+        Ok(())
+    }
+    fn overview(&self, output: &mut dyn Write) -> Result<()> {
+        self.overview_by(output, SortBy::Size)
+    }
+    fn overview_by(&self, output: &mut dyn Write, by: SortBy) -> Result<()> {
+        for list in self.storage.lists(by) {
+            writeln!(output, "  {} ({})", list.name, list.items.len())?;
+        }
+        Ok(())
+    }
+    // the original boom interface, where the meaning depends on how many args we get
+    // and whether the first one names a list or an item
+    fn boom(&mut self, output: &mut dyn Write, args: &[String]) -> Result<()> {
+        let name = &args[0];
+        // (list, item, value to print) when the args point at an existing item
+        let found = if let Some(list) = self.storage.find_list(name) {
+            if args.len() == 1 {
+                for item in &list.items {
+                    writeln!(output, "  {}: {}", item.short_name(), item.value)?;
+                }
+                return Ok(());
             }
-            Err(e) => return Err(anyhow!("failed to open db: {}", e)),
-        };
-        
-        let reader = BufReader::new(f);
-        let data = if Self::is_empty(&file)? {
-            HashMap::new()
+            match list.find_item(&args[1]) {
+                Some(item) if args.len() == 2 || item.is_template() => {
+                    Some((list.name.clone(), item.name.clone(), item.expanded(&args[2..])?))
+                },
+                Some(_) => None,
+                None if args.len() == 2 => return Err(anyhow!("'{}' not found in '{}'", args[1], name)),
+                None => None,
+            }
+        } else if let Some((list, item)) = self.storage.find_item(name) {
+            Some((list.name.clone(), item.name.clone(), item.expanded(&args[1..])?))
         } else {
-            serde_json::from_reader(reader)?
+            None
         };
-        Ok(DB { file, data })
-    }
-    fn is_empty(file: &str) -> Result<bool> {
-        let metadata = fs::metadata(file)?;
-        Ok(metadata.len() == 0)
+        if let Some((list, item, value)) = found {
+            writeln!(output, "{}", value)?;
+            return self.storage.mark_used(&list, &item);
+        }
+        match args.len() {
+            1 => {
+                self.storage.add_list(List::new(name.clone()));
+                self.storage.save()?;
+                writeln!(output, "boomr! created a new list called '{}'", name)?;
+            },
+            2 => return Err(anyhow!("list '{}' not found", name)),
+            _ => {
+                let (item_name, value) = (&args[1], args[2..].join(" "));
+                if !self.storage.list_exists(name) {
+                    self.storage.add_list(List::new(name.clone()));
+                }
+                if let Some(list) = self.storage.find_list_mut(name) {
+                    list.set_item(item_name, &value);
+                }
+                self.storage.save()?;
+                writeln!(output, "boomr! '{}' in '{}' is '{}'. got it", item_name, name, value)?;
+            },
+        }
+        Ok(())
     }
-    fn save(&mut self) -> Result<()> {
-        let json = serde_json::to_string(&self.data)?;
-        fs::write(&self.file, json)?;
+    fn find(&mut self, output: &mut dyn Write, query: &str, pick: bool) -> Result<()> {
+        let hits = self.storage.search(query);
+        if hits.is_empty() {
+            return Err(anyhow!("nothing matched '{}'", query));
+        }
+        if !pick {
+            for hit in &hits {
+                writeln!(output, "{}", Self::format_hit(hit))?;
+            }
+            return Ok(());
+        }
+        let chosen = Self::pick(&hits, &mut *self.input, output)?;
+        match chosen.item {
+            Some(item) => {
+                writeln!(output, "{}", item.value)?;
+                let (list, item) = (chosen.list.name.clone(), item.name.clone());
+                self.storage.mark_used(&list, &item)?;
+            },
+            None => {
+                for item in &chosen.list.items {
+                    writeln!(output, "  {}: {}", item.short_name(), item.value)?;
+                }
+            },
+        }
         Ok(())
     }
-    fn overview(&self) {
-        for (key, list) in &self.data {
-            println!("{} ({})", key, list.len());
+    fn format_item(list: &List, item: &Item) -> String {
+        let mut line = format!("{}/{}: {}", list.name, item.name, item.value);
+        if !item.tags.is_empty() {
+            line.push_str(&format!(" [{}]", item.tags.join(", ")));
         }
+        if let Some(description) = &item.description {
+            line.push_str(&format!(" - {}", description));
+        }
+        line
     }
-    fn create_list(&mut self, key: String) -> Result<()> {
-        self.data.insert(key.clone(), Vec::new());
-        println!("boomr! created a new list called '{}'", key);
-        self.save()
-    } 
-    fn add_to_list(&mut self, key: String, value: String) -> Result<()> {
-        self.data.entry(key).and_modify(|values| values.push(value.clone())).or_insert(vec![value]);
-        // println!("boomr! '{}' in '{}' is '{}'. got it", , )
-        Ok(())
+    fn format_hit(hit: &Hit) -> String {
+        match hit.item {
+            Some(item) => format!("{}/{}: {}", hit.list.name, item.name, item.value),
+            None => format!("{} ({})", hit.list.name, hit.list.items.len()),
+        }
+    }
+    // numbered menu on the terminal, keeps asking until it gets a valid choice
+    fn pick<'a>(hits: &'a [Hit<'a>], input: &mut dyn BufRead, output: &mut dyn Write) -> Result<&'a Hit<'a>> {
+        for (i, hit) in hits.iter().enumerate() {
+            writeln!(output, "{:>3}) {}", i + 1, Self::format_hit(hit))?;
+        }
+        loop {
+            write!(output, "pick one: ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Err(anyhow!("nothing picked"));
+            }
+            match line.trim().parse::<usize>() {
+                Ok(n) if n >= 1 && n <= hits.len() => return Ok(&hits[n - 1]),
+                _ => writeln!(output, "enter a number between 1 and {}", hits.len())?,
+            }
+        }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[derive(Debug)]
-pub struct Boomr {
-    db: DB,
-}
+    fn boomr(name: &str) -> Boomr {
+        let path = std::env::temp_dir().join(format!("bmr_run_{}_{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut boomr = Boomr::new(Storage::new(Some(path.to_string_lossy().to_string())).unwrap());
+        run(&mut boomr, &["ssh", "prod", "ssh deploy@prod.example.com"]).unwrap();
+        run(&mut boomr, &["ssh", "staging", "ssh deploy@staging.example.com"]).unwrap();
+        run(&mut boomr, &["urls", "github", "https://github.com"]).unwrap();
+        boomr
+    }
+    fn run(boomr: &mut Boomr, args: &[&str]) -> Result<String> {
+        let mut out = Vec::new();
+        boomr.run(&mut out, args.iter().map(|a| a.to_string()).collect())?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+    fn cleanup(boomr: Boomr) {
+        fs::remove_file(boomr.storage.path()).unwrap();
+    }
 
-impl Boomr {
-    pub fn new(db: DB) -> Self {
-        Boomr{ db }
+    #[test]
+    fn test_run_boom_commands() {
+        let mut boomr = boomr("boom");
+        assert_eq!("boomr! 'gitlab' in 'urls' is 'https://gitlab.com'. got it\n", run(&mut boomr, &["urls", "gitlab", "https://gitlab.com"]).unwrap());
+        assert_eq!("https://gitlab.com\n", run(&mut boomr, &["gitlab"]).unwrap());
+        assert_eq!("https://github.com\n", run(&mut boomr, &["urls", "github"]).unwrap());
+        assert_eq!("  ssh (2)\n  urls (2)\n", run(&mut boomr, &[]).unwrap());
+        assert_eq!("boomr! created a new list called 'notes'\n", run(&mut boomr, &["notes"]).unwrap());
+        assert!(run(&mut boomr, &["urls", "nope"]).is_err());
+        cleanup(boomr);
     }
-    pub fn run(&mut self, args: Vec<String>) -> Result<()> {
-        match args.len() {
-            0 => self.db.overview(),
-            1 => self.db.create_list(args[0].clone())?,
-            _ => return Err(anyhow!("too many arguments")),
-        }
-        Ok(())
+    #[test]
+    fn test_run_find() {
+        let mut boomr = boomr("find");
+        let out = run(&mut boomr, &["find", "ssh", "stag"]).unwrap();
+        assert_eq!("ssh/staging: ssh deploy@staging.example.com", out.lines().next().unwrap());
+        assert!(run(&mut boomr, &["find", "gthb"]).unwrap().contains("urls/github: https://github.com\n"));
+        assert_eq!("need a query to find", run(&mut boomr, &["find", "--pick"]).unwrap_err().to_string());
+        assert_eq!("nothing matched 'zzz'", run(&mut boomr, &["find", "zzz"]).unwrap_err().to_string());
+        cleanup(boomr);
+    }
+    #[test]
+    fn test_run_find_pick() {
+        // a bad answer gets asked again
+        let mut boomr = boomr("pick").input(Box::new(io::Cursor::new("9\nprod\n1\n")));
+        let out = run(&mut boomr, &["find", "prod", "--pick"]).unwrap();
+        assert!(out.starts_with("  1) ssh/prod: ssh deploy@prod.example.com\npick one: "));
+        assert_eq!(2, out.matches("enter a number between 1 and 1\n").count());
+        assert!(out.ends_with("pick one: ssh deploy@prod.example.com\n"));
+        assert_eq!(1, boomr.storage.find_list("ssh").unwrap().find_item("prod").unwrap().uses);
+        // picking a list shows what's in it
+        let mut boomr = boomr.input(Box::new(io::Cursor::new("1\n")));
+        let out = run(&mut boomr, &["find", "ssh", "-p"]).unwrap();
+        assert!(out.ends_with("pick one:   prod: ssh deploy@prod.example.com\n  staging: ssh deploy@staging.example.com\n"), "{}", out);
+        let mut boomr = boomr.input(Box::new(io::empty()));
+        assert_eq!("nothing picked", run(&mut boomr, &["find", "prod", "--pick"]).unwrap_err().to_string());
+        cleanup(boomr);
     }
 }
@@ -1,3 +1,5 @@
+use chrono::Utc;
+use serde_json::Value;
 use crate::item::Item;
 use super::{
     HashMap,
@@ -5,7 +7,10 @@ use super::{
     Deserialize,
 };
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+// the boom file format for a list, {"name": [{"item": "value"}, ...]}
+pub type ListHash = HashMap<String, Vec<HashMap<String, Value>>>;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct List {
     pub name: String,
     pub items: Vec<Item>,
@@ -22,6 +27,34 @@ impl List {
         self.delete_item(&item.name);
         self.items.push(item)
     }
+    /// adds or updates an item by name, keeping the tags and history of one that's already there.
+    pub fn set_item(&mut self, name: &str, value: &str) {
+        let now = Some(Utc::now());
+        match self.items.iter_mut().find(|i| i.name == name) {
+            Some(item) => {
+                item.value = value.to_string();
+                item.updated_at = now;
+            },
+            None => {
+                let mut item = Item::new(name.to_string(), value.to_string());
+                item.created_at = now;
+                item.updated_at = now;
+                self.items.push(item);
+            },
+        }
+    }
+    pub fn find_item_mut(&mut self, name: &str) -> Option<&mut Item> {
+        self.items.iter_mut().find(|i| {
+            i.name == name || i.short_name().replace("...", "") == name
+        })
+    }
+    /// the last time anything in the list was used or changed.
+    pub fn last_active(&self) -> Option<chrono::DateTime<Utc>> {
+        self.items.iter().filter_map(|i| i.last_active()).max()
+    }
+    pub fn uses(&self) -> u64 {
+        self.items.iter().map(|i| i.uses).sum()
+    }
     pub fn find_item(&self, name: &str) -> Option<&Item> {
         self.items.iter().find(|i| {
             i.name == name || i.short_name().replace("...", "") == name
@@ -30,7 +63,7 @@ impl List {
     pub fn delete_item(&mut self, name: &str) {
         self.items.retain(|i| i.name != name)
     }
-    pub fn to_hash(&self) -> HashMap<String, Vec<HashMap<String, String>>> {
+    pub fn to_hash(&self) -> ListHash {
         let mut map = HashMap::new();
         map.insert(self.name.clone(), self.items.iter().map(|item| item.to_hash()).collect());
         map
@@ -90,6 +123,24 @@ mod tests {
         list.add_item(item);
         assert!(list.find_item("this is a very ").is_some());
         assert!(list.find_item("blah").is_none());
+        // ü is two bytes, so byte 15 is inside the ß
+        list.add_item(Item::new("münchner straße ist lang".to_string(), "mvg.de".to_string()));
+        assert!(list.find_item_mut("münchner straße").is_some());
+        assert!(list.find_item("münchner straße ist lang").is_some());
+        assert!(list.find_item_mut("nope").is_none());
+    }
+    #[test]
+    fn test_set_item_keeps_metadata() {
+        let mut list = List::new("foo".to_string());
+        list.set_item("github", "github.com/foo");
+        let created = list.items[0].created_at;
+        assert!(created.is_some());
+        list.items[0].add_tag("code");
+        list.set_item("github", "github.com/bar");
+        assert_eq!(1, list.items.len());
+        assert_eq!("github.com/bar", list.items[0].value);
+        assert_eq!(created, list.items[0].created_at);
+        assert!(list.items[0].has_tag("code"));
     }
     #[test]
     fn test_to_hash() {
@@ -97,7 +148,7 @@ mod tests {
         let item = Item::new("key".to_string(), "value".to_string());
         list.add_item(item);
         let expected = HashMap::from([
-            ("foo".to_string(), vec![HashMap::from([("key".to_string(), "value".to_string())])])
+            ("foo".to_string(), vec![HashMap::from([("key".to_string(), Value::String("value".to_string()))])])
         ]);
         assert_eq!(list.to_hash(), expected);
     }
@@ -1,16 +1,18 @@
 use std::env::args;
-use bmr::{Boomr, DB};
+use std::io;
+use bmr::Boomr;
+use bmr::storage::Storage;
 
 fn main() {
-    let db = match DB::connect("foo.json".to_string()) {
-        Ok(d) => d,
+    let storage = match Storage::new(None) {
+        Ok(s) => s,
         Err(e) => {
             eprintln!("{e}");
-            std::process::exit(1)    
+            std::process::exit(1)
         }
     };
     let args = args().skip(1).collect();
-    if let Err(e) = Boomr::new(db).run(args) {
+    if let Err(e) = Boomr::new(storage).run(&mut io::stdout(), args) {
         eprintln!("{e}");
         std::process::exit(1)
     }
@@ -1,6 +1,6 @@
 use std::io::{Read, Write};
-use std::path::PathBuf;
-use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::fs::{File, OpenOptions};
 use serde_json::Value;
 
 use super::{
@@ -9,39 +9,111 @@ use super::{
     Serialize,
     Result,
 };
-use crate::list::List;
+use crate::fuzzy;
+use crate::list::{List, ListHash};
 use crate::item::Item;
 
 #[derive(Serialize, Deserialize, Debug, Default)]
-struct Storage {
+pub struct Storage {
     json_file_path: PathBuf,
     lists: Vec<List>,
 }
 
+/// how lists get ordered in the overview.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    Size,
+    Recent,
+    Usage,
+}
+
+impl std::str::FromStr for SortBy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "size" => Ok(SortBy::Size),
+            "recent" => Ok(SortBy::Recent),
+            "usage" => Ok(SortBy::Usage),
+            _ => Err(anyhow::anyhow!("sort by size, recent, or usage")),
+        }
+    }
+}
+
+/// a single result from a fuzzy search. list hits have no item.
+#[derive(Debug)]
+pub struct Hit<'a> {
+    pub list: &'a List,
+    pub item: Option<&'a Item>,
+    pub score: i64,
+}
+
 impl Storage {
     const DEFAULT_JSON_FILE: &'static str = "/.boomr";
-    pub fn new(custom_path: Option<String>) -> Self {
-        let mut storage = Storage::default();
-        storage.json_file_path = match custom_path {
-            Some(path) => PathBuf::from(path),
-            None => Storage::json_file(),
+    pub fn new(custom_path: Option<String>) -> Result<Self> {
+        let mut storage = Storage {
+            json_file_path: match custom_path {
+                Some(path) => PathBuf::from(path),
+                None => Storage::json_file(),
+            },
+            ..Default::default()
         };
-        storage.bootstrap();
-        storage.populate();
-        storage
+        storage.bootstrap()?;
+        storage.populate()?;
+        Ok(storage)
     }
     pub fn json_file() -> PathBuf {
         let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
         PathBuf::from(format!("{}{}", home, Storage::DEFAULT_JSON_FILE))
     }
-    pub fn lists(&self) -> Vec<&List> {
+    pub fn path(&self) -> &Path {
+        &self.json_file_path
+    }
+    /// every list, in the order they're stored.
+    pub fn all_lists(&self) -> &[List] {
+        &self.lists
+    }
+    pub fn set_lists(&mut self, lists: Vec<List>) {
+        self.lists = lists;
+    }
+    pub fn lists(&self, by: SortBy) -> Vec<&List> {
         let mut sorted_lists = self.lists.iter().collect::<Vec<&List>>();
-        sorted_lists.sort_by(|a,b| b.items.len().cmp(&a.items.len()));
+        match by {
+            SortBy::Size => sorted_lists.sort_by_key(|l| std::cmp::Reverse(l.items.len())),
+            SortBy::Recent => sorted_lists.sort_by_key(|l| std::cmp::Reverse(l.last_active())),
+            SortBy::Usage => sorted_lists.sort_by_key(|l| std::cmp::Reverse(l.uses())),
+        }
         sorted_lists
     }
     pub fn list_exists(&self, name: &str) -> bool {
         self.lists.iter().any(|n| n.name == name)
     }
+    pub fn find_list(&self, name: &str) -> Option<&List> {
+        self.lists.iter().find(|l| l.name == name)
+    }
+    pub fn find_list_mut(&mut self, name: &str) -> Option<&mut List> {
+        self.lists.iter_mut().find(|l| l.name == name)
+    }
+    pub fn add_list(&mut self, list: List) {
+        self.lists.retain(|l| l.name != list.name);
+        self.lists.push(list);
+    }
+    /// adds lists from somewhere else into the store, items with the same
+    /// name in the same list get overwritten. returns how many items came in.
+    pub fn import_lists(&mut self, lists: Vec<List>) -> usize {
+        let mut count = 0;
+        for list in lists {
+            count += list.items.len();
+            match self.find_list_mut(&list.name) {
+                Some(existing) => {
+                    for item in list.items {
+                        existing.add_item(item);
+                    }
+                },
+                None => self.lists.push(list),
+            }
+        }
+        count
+    }
     pub fn items(&self) -> Vec<&Item> {
         self.lists.iter()
             .flat_map(|list| &list.items)
@@ -50,33 +122,90 @@ impl Storage {
     pub fn item_exists(&self, name: &str) -> bool {
         self.items().iter().any(|item| item.name == name)
     }
-    pub fn to_hash(&self) -> HashMap<String, Vec<HashMap<String, String>>> {
+    /// the first item with the given name in any list, along with its list.
+    pub fn find_item(&self, name: &str) -> Option<(&List, &Item)> {
+        self.lists.iter()
+            .find_map(|list| list.items.iter().find(|i| i.name == name).map(|i| (list, i)))
+    }
+    pub fn find_item_mut(&mut self, name: &str) -> Option<&mut Item> {
+        self.lists.iter_mut()
+            .find_map(|list| list.items.iter_mut().find(|i| i.name == name))
+    }
+    pub fn tagged(&self, tag: &str) -> Vec<(&List, &Item)> {
+        self.lists.iter()
+            .flat_map(|list| list.items.iter().map(move |i| (list, i)))
+            .filter(|(_, i)| i.has_tag(tag))
+            .collect()
+    }
+    /// the most recently used or changed items, newest first.
+    pub fn recent(&self, n: usize) -> Vec<(&List, &Item)> {
+        let mut items = self.lists.iter()
+            .flat_map(|list| list.items.iter().map(move |i| (list, i)))
+            .filter(|(_, i)| i.last_active().is_some())
+            .collect::<Vec<_>>();
+        items.sort_by_key(|(_, i)| std::cmp::Reverse(i.last_active()));
+        items.truncate(n);
+        items
+    }
+    /// records that an item was used and saves.
+    pub fn mark_used(&mut self, list: &str, item: &str) -> Result<()> {
+        if let Some(item) = self.find_list_mut(list).and_then(|l| l.find_item_mut(item)) {
+            item.mark_used();
+        }
+        self.save()
+    }
+    /// fuzzy search across list names, item names, and item values.
+    /// results come back best match first.
+    pub fn search(&self, query: &str) -> Vec<Hit<'_>> {
+        let mut hits = Vec::new();
+        for list in &self.lists {
+            if let Some(score) = fuzzy::score(query, &list.name) {
+                hits.push(Hit { list, item: None, score });
+            }
+            for item in &list.items {
+                // values tend to be long and noisy, so they count for less than names.
+                // scoring "list item" lets queries like "ssh prod" hit prod in ssh.
+                let best = [
+                    fuzzy::score(query, &item.name),
+                    fuzzy::score(query, &format!("{} {}", list.name, item.name)),
+                    fuzzy::score(query, &item.value).map(|s| s / 2),
+                ].into_iter().flatten().max();
+                if let Some(score) = best {
+                    hits.push(Hit { list, item: Some(item), score });
+                }
+            }
+        }
+        hits.sort_by(|a, b| {
+            b.score.cmp(&a.score)
+                .then_with(|| a.list.name.cmp(&b.list.name))
+                .then_with(|| a.item.map(|i| &i.name).cmp(&b.item.map(|i| &i.name)))
+        });
+        hits
+    }
+    pub fn to_hash(&self) -> HashMap<String, Vec<ListHash>> {
         let mut map = HashMap::new();
-        let lists_vec: Vec<HashMap<String, String>> = self.lists.iter().map(|list| {
-            let list_hash = list.to_hash();
-            list_hash.into_iter().map(|(name, items)| {
-                let items_str = items.into_iter().flat_map(|item| item.into_iter()).collect::<HashMap<_, _>>();
-                HashMap::from([(name, serde_json::to_string(&items_str).unwrap())])
-            }).collect::<Vec<_>>()
-        }).flatten().collect();
-    
-        map.insert("lists".to_string(), lists_vec);
+        map.insert("lists".to_string(), self.lists.iter().map(|list| list.to_hash()).collect());
         map
-    }    
+    }
     fn bootstrap(&self) -> Result<()> {
         let path = &self.json_file_path;
         if !path.exists() || path.metadata().map(|m| m.len()).unwrap_or(0) == 0 {
-            let _ = File::create(&path).and_then(|mut file| file.write_all(b"{}"));
-            self.save();
+            File::create(path)?.write_all(b"{}")?;
+            self.save()?;
         }
         Ok(())
     }
-    fn populate(&mut self) {
-        let path = &self.json_file_path;
-        let mut file = File::open(path).expect("failed to open json");
+    fn populate(&mut self) -> Result<()> {
+        let mut file = File::open(&self.json_file_path)?;
         let mut data = String::new();
-        file.read_to_string(&mut data).expect("failed to read json");
-        let parsed: Value = serde_json::from_str(&data).expect("invalid json");
+        file.read_to_string(&mut data)?;
+        self.lists = Storage::parse(&data)?;
+        Ok(())
+    }
+    /// reads lists out of boom formatted json.
+    pub fn parse(data: &str) -> Result<Vec<List>> {
+        let parsed: Value = serde_json::from_str(data)?;
+        let mut out = Vec::new();
         if let Some(lists) = parsed.get("lists").and_then(Value::as_array) {
             for list in lists {
                 if let Some(list_name) = list.as_object() {
@@ -86,25 +215,131 @@ impl Storage {
                             for item in items_array {
                                 if let Some(item_obj) = item.as_object() {
                                     for (item_name, value) in item_obj {
-                                        list_instance.add_item(Item::new(item_name.clone(), value.as_str().unwrap_or("").to_string()));
+                                        let item = Item::from_value(item_name, value)
+                                            .map_err(|e| anyhow::anyhow!("list '{}', item '{}': {}", name, item_name, e))?;
+                                        list_instance.add_item(item);
                                     }
                                 }
                             }
                         }
-                        self.lists.push(list_instance);
+                        out.push(list_instance);
                     }
                 }
             }
         }
+        Ok(out)
     }
-    pub fn save(&self) {
+    pub fn save(&self) -> Result<()> {
         let path = &self.json_file_path;
-        let json_data = serde_json::to_string_pretty(&self.to_hash()).expect("failed to convert to JSON");
-        let mut file = OpenOptions::new().write(true).truncate(true).open(path).expect("unable to open JSON file for writing");
-        file.write_all(json_data.as_bytes()).expect("unable to write data to file");
+        let json_data = self.to_json()?;
+        let mut file = OpenOptions::new().write(true).truncate(true).open(path)?;
+        file.write_all(json_data.as_bytes())?;
+        Ok(())
     }
 
-    pub fn to_json(&self) -> String {
-        serde_json::to_string_pretty(&self.to_hash()).expect("failed to convert to JSON")
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.to_hash())?)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_storage(name: &str) -> Storage {
+        let path = std::env::temp_dir().join(format!("bmr_{}_{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Storage::new(Some(path.to_string_lossy().to_string())).expect("failed to create storage")
+    }
+
+    #[test]
+    fn test_save_and_populate() {
+        let mut storage = temp_storage("save");
+        let mut list = List::new("urls".to_string());
+        list.add_item(Item::new("github".to_string(), "https://github.com".to_string()));
+        storage.add_list(list);
+        storage.save().unwrap();
+        let reloaded = Storage::new(Some(storage.json_file_path.to_string_lossy().to_string())).unwrap();
+        assert!(reloaded.list_exists("urls"));
+        assert!(reloaded.item_exists("github"));
+        assert_eq!("https://github.com", reloaded.items()[0].value);
+        std::fs::remove_file(&storage.json_file_path).unwrap();
+    }
+    #[test]
+    fn test_populate_plain_and_metadata_items() {
+        let path = std::env::temp_dir().join(format!("bmr_compat_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"lists":[{"urls":[{"github":"https://github.com"},{"lobsters":{"value":"https://lobste.rs","tags":["news"],"uses":3}}]}]}"#).unwrap();
+        let storage = Storage::new(Some(path.to_string_lossy().to_string())).unwrap();
+        let list = storage.find_list("urls").unwrap();
+        assert_eq!("https://github.com", list.items[0].value);
+        assert!(list.items[0].tags.is_empty());
+        assert!(list.items[1].has_tag("news"));
+        assert_eq!(3, list.uses());
+        assert_eq!(1, storage.tagged("news").len());
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn test_populate_bad_item_fails() {
+        let path = std::env::temp_dir().join(format!("bmr_bad_item_{}.json", std::process::id()));
+        let data = r#"{"lists":[{"urls":[{"github":"https://github.com"},{"old":{"url":"https://example.com"}}]}]}"#;
+        std::fs::write(&path, data).unwrap();
+        let err = Storage::new(Some(path.to_string_lossy().to_string())).err().unwrap();
+        assert!(err.to_string().starts_with("list 'urls', item 'old': "), "{}", err);
+        assert!(Storage::parse(r#"{"lists":[{"urls":[{"n":7}]}]}"#).is_err());
+        // and the file is left as it was
+        assert_eq!(data, std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn test_import_lists() {
+        let mut storage = temp_storage("import");
+        let mut list = List::new("urls".to_string());
+        list.add_item(Item::new("github".to_string(), "old".to_string()));
+        storage.add_list(list);
+        let mut urls = List::new("urls".to_string());
+        urls.add_item(Item::new("github".to_string(), "new".to_string()));
+        urls.add_item(Item::new("lobsters".to_string(), "https://lobste.rs".to_string()));
+        let count = storage.import_lists(vec![urls, List::new("empty".to_string())]);
+        assert_eq!(2, count);
+        assert_eq!(2, storage.all_lists().len());
+        assert_eq!(2, storage.find_list("urls").unwrap().items.len());
+        assert_eq!("new", storage.find_list("urls").unwrap().find_item("github").unwrap().value);
+        std::fs::remove_file(&storage.json_file_path).unwrap();
+    }
+    #[test]
+    fn test_lists_sort_and_recent() {
+        let mut storage = temp_storage("sort");
+        let mut big = List::new("big".to_string());
+        big.add_item(Item::new("a".to_string(), "1".to_string()));
+        big.add_item(Item::new("b".to_string(), "2".to_string()));
+        storage.add_list(big);
+        let mut small = List::new("small".to_string());
+        small.set_item("c", "3");
+        storage.add_list(small);
+        assert_eq!("big", storage.lists(SortBy::Size)[0].name);
+        assert_eq!("small", storage.lists(SortBy::Recent)[0].name);
+        storage.mark_used("big", "a").unwrap();
+        assert_eq!("big", storage.lists(SortBy::Usage)[0].name);
+        let recent = storage.recent(10);
+        assert_eq!(2, recent.len());
+        assert_eq!("a", recent[0].1.name);
+        std::fs::remove_file(&storage.json_file_path).unwrap();
+    }
+    #[test]
+    fn test_search_ranks_names_first() {
+        let mut storage = temp_storage("search");
+        let mut list = List::new("ssh".to_string());
+        list.add_item(Item::new("prod".to_string(), "ssh deploy@prod.example.com".to_string()));
+        list.add_item(Item::new("staging".to_string(), "ssh deploy@staging.example.com".to_string()));
+        storage.add_list(list);
+        let hits = storage.search("prod");
+        assert_eq!(1, hits.len());
+        assert_eq!("prod", hits[0].item.unwrap().name);
+        let hits = storage.search("ssh stag");
+        assert_eq!("staging", hits[0].item.unwrap().name);
+        let hits = storage.search("ssh");
+        assert!(hits[0].item.is_none());
+        assert!(storage.search("zzz").is_empty());
+        std::fs::remove_file(&storage.json_file_path).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+use anyhow::{Result, anyhow};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::item::Item;
+use crate::list::List;
+use crate::storage::Storage;
+
+const SYNC_FILE: &str = "boomr.json";
+// the commit we last synced with, used as the base for merges. a fresh clone
+// doesn't have it yet, so the first sync treats everything as added.
+const BASE_REF: &str = "refs/bmr/base";
+
+/// an item that changed on both sides (or changed on one and was deleted on the other).
+/// the local version always wins, so nothing gets clobbered without a heads up.
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub list: String,
+    pub item: String,
+    pub local: Option<String>,
+    pub remote: Option<String>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |v: &Option<String>| v.as_ref().map(|v| format!("'{}'", v)).unwrap_or("deleted".to_string());
+        write!(f, "{}/{}: local {}, remote {}", self.list, self.item, show(&self.local), show(&self.remote))
+    }
+}
+
+/// keeps the json store in sync through a git clone that lives next to it,
+/// e.g. ~/.boomr is synced through ~/.boomr-sync.
+pub struct Sync {
+    dir: PathBuf,
+}
+
+impl Sync {
+    pub fn new(store: &Path) -> Self {
+        let mut dir = store.as_os_str().to_owned();
+        dir.push("-sync");
+        Sync { dir: PathBuf::from(dir) }
+    }
+    pub fn init(&self, repo: &str) -> Result<()> {
+        if self.dir.exists() {
+            return Err(anyhow!("sync is already set up in {}", self.dir.display()));
+        }
+        let out = Command::new("git").arg("clone").arg("--quiet").arg(repo).arg(&self.dir).output()?;
+        if !out.status.success() {
+            return Err(anyhow!("git clone failed: {}", String::from_utf8_lossy(&out.stderr).trim()));
+        }
+        Ok(())
+    }
+    /// merges in whatever is on the remote, then pushes the result. bails on conflicts
+    /// so the remote side isn't clobbered, pull first to keep the local versions.
+    pub fn push(&self, storage: &mut Storage) -> Result<()> {
+        let (merged, conflicts) = self.merge(storage)?;
+        if !conflicts.is_empty() {
+            let report = conflicts.iter().map(|c| format!("  {}", c)).collect::<Vec<String>>().join("\n");
+            return Err(anyhow!("push aborted, {} conflict(s):\n{}\nrun `bmr sync pull` to keep the local versions, then push again", conflicts.len(), report));
+        }
+        self.reset_to_remote()?;
+        storage.set_lists(merged);
+        storage.save()?;
+        fs::write(self.dir.join(SYNC_FILE), storage.to_json()?)?;
+        self.git(&["add", SYNC_FILE])?;
+        if !self.git(&["status", "--porcelain"])?.trim().is_empty() {
+            self.commit("bmr sync")?;
+            self.git(&["push", "--quiet", "origin", &format!("HEAD:{}", self.branch()?)])?;
+        }
+        self.mark_synced()
+    }
+    /// merges the remote into the local store. conflicts keep the local version
+    /// and are handed back so they can be reported.
+    pub fn pull(&self, storage: &mut Storage) -> Result<Vec<Conflict>> {
+        let (merged, conflicts) = self.merge(storage)?;
+        storage.set_lists(merged);
+        storage.save()?;
+        self.reset_to_remote()?;
+        self.mark_synced()?;
+        Ok(conflicts)
+    }
+    fn merge(&self, storage: &Storage) -> Result<(Vec<List>, Vec<Conflict>)> {
+        if !self.dir.exists() {
+            return Err(anyhow!("sync isn't set up, run `bmr sync init <repo-path>` first"));
+        }
+        self.git(&["fetch", "--quiet", "origin"])?;
+        let base = self.read_at(BASE_REF)?;
+        let remote = self.read_at(&format!("origin/{}", self.branch()?))?;
+        Ok(merge(&base, storage.all_lists(), &remote))
+    }
+    // the store as of a given commit, empty if there's no commit yet
+    fn read_at(&self, rev: &str) -> Result<Vec<List>> {
+        match self.git(&["show", &format!("{}:{}", rev, SYNC_FILE)]) {
+            Ok(data) => Storage::parse(&data),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+    fn reset_to_remote(&self) -> Result<()> {
+        let remote = format!("origin/{}", self.branch()?);
+        if self.git(&["rev-parse", "--verify", "--quiet", &remote]).is_ok() {
+            self.git(&["reset", "--quiet", "--hard", &remote])?;
+        }
+        Ok(())
+    }
+    fn mark_synced(&self) -> Result<()> {
+        if self.git(&["rev-parse", "--verify", "--quiet", "HEAD"]).is_ok() {
+            self.git(&["update-ref", BASE_REF, "HEAD"])?;
+        }
+        Ok(())
+    }
+    fn branch(&self) -> Result<String> {
+        Ok(self.git(&["symbolic-ref", "--short", "HEAD"])?.trim().to_string())
+    }
+    fn commit(&self, message: &str) -> Result<()> {
+        // don't override anyone's identity, but don't fail on machines without one either
+        if self.git(&["config", "user.email"]).is_ok() {
+            self.git(&["commit", "--quiet", "-m", message])?;
+        } else {
+            self.git(&["-c", "user.name=bmr", "-c", "user.email=bmr@localhost", "commit", "--quiet", "-m", message])?;
+        }
+        Ok(())
+    }
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let out = Command::new("git").arg("-C").arg(&self.dir).args(args).output()?;
+        if !out.status.success() {
+            return Err(anyhow!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&out.stderr).trim()));
+        }
+        Ok(String::from_utf8_lossy(&out.stdout).to_string())
+    }
+}
+
+/// three way merge of lists and items, keyed by name. whichever side changed
+/// something wins, and if both changed it the same way there's nothing to do.
+/// anything else is a conflict, which keeps the local version.
+pub fn merge(base: &[List], local: &[List], remote: &[List]) -> (Vec<List>, Vec<Conflict>) {
+    let mut merged = Vec::new();
+    let mut conflicts = Vec::new();
+    for name in union(local.iter().map(|l| &l.name), remote.iter().map(|l| &l.name)) {
+        let find = |lists: &[List]| lists.iter().find(|l| l.name == name).cloned();
+        let (b, l, r) = (find(base), find(local), find(remote));
+        let items = |list: &Option<List>| list.as_ref().map(|l| l.items.clone()).unwrap_or_default();
+        let (b_items, l_items, r_items) = (items(&b), items(&l), items(&r));
+        let mut out = List::new(name.clone());
+        for item_name in union(l_items.iter().map(|i| &i.name), r_items.iter().map(|i| &i.name)) {
+            let find = |items: &[Item]| items.iter().find(|i| i.name == item_name).cloned();
+            let (bi, li, ri) = (find(&b_items), find(&l_items), find(&r_items));
+            let (bc, lc, rc) = (content(&bi), content(&li), content(&ri));
+            let keep = if lc == rc || rc == bc {
+                li.clone()
+            } else if lc == bc {
+                ri.clone()
+            } else {
+                conflicts.push(Conflict {
+                    list: name.clone(),
+                    item: item_name.clone(),
+                    local: li.as_ref().map(|i| i.value.clone()),
+                    remote: ri.as_ref().map(|i| i.value.clone()),
+                });
+                li.clone()
+            };
+            if let Some(mut item) = keep {
+                // usage changes just by reading, so it never conflicts, the busiest side wins
+                let sides = [&li, &ri];
+                item.last_used = sides.iter().filter_map(|i| i.as_ref().and_then(|i| i.last_used)).max();
+                item.uses = sides.iter().filter_map(|i| i.as_ref().map(|i| i.uses)).max().unwrap_or(0);
+                out.items.push(item);
+            }
+        }
+        // a list deleted on one side goes away, unless the other side still has items in it
+        let exists = if l.is_some() == r.is_some() || r.is_some() == b.is_some() { l.is_some() } else { r.is_some() };
+        if exists || !out.items.is_empty() {
+            merged.push(out);
+        }
+    }
+    (merged, conflicts)
+}
+
+// an item without its usage stats, which is what merges compare on
+fn content(item: &Option<Item>) -> Option<Item> {
+    item.clone().map(|mut i| {
+        i.last_used = None;
+        i.uses = 0;
+        i
+    })
+}
+
+// names from both sides, local order first
+fn union<'a>(a: impl Iterator<Item = &'a String>, b: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for name in a.chain(b) {
+        if !out.contains(name) {
+            out.push(name.clone());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(name: &str, items: &[(&str, &str)]) -> List {
+        let mut list = List::new(name.to_string());
+        for (k, v) in items {
+            list.add_item(Item::new(k.to_string(), v.to_string()));
+        }
+        list
+    }
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bmr_sync_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_merge_one_side_changed() {
+        let base = vec![list("ssh", &[("prod", "a"), ("dev", "b")])];
+        let local = vec![list("ssh", &[("prod", "a2"), ("dev", "b")])];
+        let remote = vec![list("ssh", &[("prod", "a"), ("dev", "b2"), ("qa", "c")]), list("urls", &[])];
+        let (merged, conflicts) = merge(&base, &local, &remote);
+        assert!(conflicts.is_empty());
+        assert_eq!(vec![list("ssh", &[("prod", "a2"), ("dev", "b2"), ("qa", "c")]), list("urls", &[])], merged);
+    }
+    #[test]
+    fn test_merge_deletes() {
+        let base = vec![list("ssh", &[("prod", "a"), ("dev", "b")]), list("old", &[("x", "y")])];
+        let local = vec![list("ssh", &[("prod", "a")]), list("old", &[("x", "y")])];
+        let remote = vec![list("ssh", &[("prod", "a"), ("dev", "b")])];
+        let (merged, conflicts) = merge(&base, &local, &remote);
+        assert!(conflicts.is_empty());
+        assert_eq!(vec![list("ssh", &[("prod", "a")])], merged);
+    }
+    #[test]
+    fn test_merge_conflicts_keep_local() {
+        let base = vec![list("ssh", &[("prod", "a"), ("dev", "b")])];
+        let local = vec![list("ssh", &[("prod", "local"), ("dev", "b2")])];
+        let remote = vec![list("ssh", &[("prod", "remote")])];
+        let (merged, conflicts) = merge(&base, &local, &remote);
+        assert_eq!(vec![list("ssh", &[("prod", "local"), ("dev", "b2")])], merged);
+        assert_eq!(2, conflicts.len());
+        assert_eq!("ssh/prod: local 'local', remote 'remote'", conflicts[0].to_string());
+        assert_eq!("ssh/dev: local 'b2', remote deleted", conflicts[1].to_string());
+    }
+    #[test]
+    fn test_merge_usage_never_conflicts() {
+        let base = vec![list("ssh", &[("prod", "a")])];
+        let mut local = base.clone();
+        local[0].items[0].mark_used();
+        let mut remote = base.clone();
+        remote[0].items[0].mark_used();
+        remote[0].items[0].mark_used();
+        let (merged, conflicts) = merge(&base, &local, &remote);
+        assert!(conflicts.is_empty());
+        assert_eq!(2, merged[0].items[0].uses);
+        assert_eq!(remote[0].items[0].last_used, merged[0].items[0].last_used);
+    }
+    #[test]
+    fn test_sync_through_bare_repo() {
+        let dir = temp_dir("bare");
+        let remote = dir.join("remote.git");
+        let status = Command::new("git").args(["init", "--quiet", "--bare"]).arg(&remote).status().unwrap();
+        assert!(status.success());
+        let laptop = |name: &str| Storage::new(Some(dir.join(name).to_string_lossy().to_string())).unwrap();
+
+        let mut work = laptop("work");
+        work.add_list(list("ssh", &[("prod", "ssh prod"), ("dev", "ssh dev")]));
+        let work_sync = Sync::new(work.path());
+        work_sync.init(&remote.to_string_lossy()).unwrap();
+        work_sync.push(&mut work).unwrap();
+
+        let mut home = laptop("home");
+        let home_sync = Sync::new(home.path());
+        home_sync.init(&remote.to_string_lossy()).unwrap();
+        assert!(home_sync.pull(&mut home).unwrap().is_empty());
+        assert_eq!(2, home.items().len());
+
+        // both edit, different items merge cleanly
+        home.find_list_mut("ssh").unwrap().add_item(Item::new("dev".to_string(), "ssh dev2".to_string()));
+        home_sync.push(&mut home).unwrap();
+        work.find_list_mut("ssh").unwrap().add_item(Item::new("qa".to_string(), "ssh qa".to_string()));
+        assert!(work_sync.pull(&mut work).unwrap().is_empty());
+        assert_eq!("ssh dev2", work.find_list("ssh").unwrap().find_item("dev").unwrap().value);
+        assert!(work.item_exists("qa"));
+        work_sync.push(&mut work).unwrap();
+
+        // same item on both sides is a conflict, push refuses and pull keeps local
+        home.find_list_mut("ssh").unwrap().add_item(Item::new("prod".to_string(), "home".to_string()));
+        work_sync.pull(&mut work).unwrap();
+        work.find_list_mut("ssh").unwrap().add_item(Item::new("prod".to_string(), "work".to_string()));
+        work_sync.push(&mut work).unwrap();
+        assert!(home_sync.push(&mut home).is_err());
+        let conflicts = home_sync.pull(&mut home).unwrap();
+        assert_eq!(1, conflicts.len());
+        assert_eq!("prod", conflicts[0].item);
+        assert!(home.item_exists("qa"));
+        home_sync.push(&mut home).unwrap();
+        work_sync.pull(&mut work).unwrap();
+        assert_eq!("home", work.find_list("ssh").unwrap().find_item("prod").unwrap().value);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
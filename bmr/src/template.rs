@@ -0,0 +1,105 @@
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use std::fmt::Write;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// true if the value has at least one {{...}} placeholder in it.
+pub fn has_placeholders(value: &str) -> bool {
+    value.find("{{").is_some_and(|start| value[start..].contains("}}"))
+}
+
+/// fills in the placeholders in a stored value:
+/// {{1}}, {{2}}, ...       the extra args given on the command line
+/// {{env:NAME}}            an environment variable
+/// {{date}}                today's date, or {{date:%Y%m%d}} with a chrono format
+pub fn expand(value: &str, args: &[String]) -> Result<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let placeholder = rest[start + 2..start + 2 + len].trim();
+        out.push_str(&resolve(placeholder, args)?);
+        rest = &rest[start + 2 + len + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn resolve(placeholder: &str, args: &[String]) -> Result<String> {
+    if let Ok(n) = placeholder.parse::<usize>() {
+        if n == 0 {
+            return Err(anyhow!("placeholders start at {{{{1}}}}"));
+        }
+        return args.get(n - 1).cloned().ok_or_else(|| anyhow!("missing argument {} for {{{{{}}}}}", n, n));
+    }
+    if let Some(name) = placeholder.strip_prefix("env:") {
+        return std::env::var(name).map_err(|_| anyhow!("environment variable '{}' isn't set", name));
+    }
+    if placeholder == "date" {
+        return Ok(Local::now().format(DEFAULT_DATE_FORMAT).to_string());
+    }
+    if let Some(format) = placeholder.strip_prefix("date:") {
+        // to_string would panic on a bad format
+        let mut out = String::new();
+        write!(out, "{}", Local::now().format(format)).map_err(|_| anyhow!("bad date format '{}'", format))?;
+        return Ok(out);
+    }
+    Err(anyhow!("unknown placeholder {{{{{}}}}}", placeholder))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_has_placeholders() {
+        assert!(has_placeholders("ssh {{1}}"));
+        assert!(!has_placeholders("ssh prod"));
+        assert!(!has_placeholders("ssh {{1"));
+    }
+    #[test]
+    fn test_expand_positional() {
+        let got = expand("ssh deploy@{{1}}.example.com -p {{2}}", &args(&["web3", "2222"])).unwrap();
+        assert_eq!("ssh deploy@web3.example.com -p 2222", got);
+    }
+    #[test]
+    fn test_expand_repeated_and_spaced() {
+        let got = expand("{{ 1 }}-{{1}}", &args(&["a"])).unwrap();
+        assert_eq!("a-a", got);
+    }
+    #[test]
+    fn test_expand_missing_arg() {
+        assert!(expand("ssh {{2}}", &args(&["web3"])).is_err());
+        assert!(expand("ssh {{0}}", &args(&["web3"])).is_err());
+    }
+    #[test]
+    fn test_expand_env() {
+        std::env::set_var("BMR_TEMPLATE_TEST", "bar");
+        assert_eq!("foo/bar", expand("foo/{{env:BMR_TEMPLATE_TEST}}", &[]).unwrap());
+        assert!(expand("{{env:BMR_TEMPLATE_TEST_UNSET}}", &[]).is_err());
+    }
+    #[test]
+    fn test_expand_date() {
+        let today = Local::now().format("%Y%m%d").to_string();
+        assert_eq!(format!("backup-{}", today), expand("backup-{{date:%Y%m%d}}", &[]).unwrap());
+        assert_eq!(10, expand("{{date}}", &[]).unwrap().len());
+    }
+    #[test]
+    fn test_expand_bad_date_format() {
+        let err = expand("backup-{{date:%Q}}.tar", &[]).unwrap_err();
+        assert_eq!("bad date format '%Q'", err.to_string());
+    }
+    #[test]
+    fn test_expand_unknown_and_unclosed() {
+        assert!(expand("{{nope}}", &[]).is_err());
+        assert_eq!("echo {{1", expand("echo {{1", &[]).unwrap());
+    }
+}
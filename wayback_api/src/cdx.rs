@@ -0,0 +1,120 @@
+// rows from the cdx endpoint, one per capture
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::date::DATE_FORMAT;
+use crate::table::{field, FromRow};
+
+pub const WAYBACK_WEB_URL_BASE: &str = "http://web.archive.org/web/";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CdxInfo {
+    pub original: String,
+    pub archived_at: NaiveDateTime,
+    pub status_code: Option<u16>,
+    pub mime_type: String,
+    pub digest: String,
+    pub length: u64,
+}
+
+impl CdxInfo {
+    pub fn parse_row(
+        original: &str,
+        timestamp: &str,
+        status_code: &str,
+        mime_type: &str,
+        digest: &str,
+        length: &str,
+    ) -> Result<CdxInfo> {
+        let archived_at = NaiveDateTime::parse_from_str(timestamp, DATE_FORMAT)?;
+        let length = if length == "-" { 0 } else { length.parse::<u64>()? };
+        // wb uses "-" for unknown/null values
+        let status_code = if status_code == "-" { None } else { 
+            Some(status_code.parse::<u16>()?)
+        };
+        Ok(CdxInfo{
+            original: original.to_string(),
+            archived_at,
+            status_code,
+            mime_type: mime_type.to_string(),
+            digest: digest.to_string(),
+            length,
+        })
+    }
+    /// a row from a query with any fl, fields being the header row that came with it.
+    /// original and timestamp have to be there, anything else missing is left unknown.
+    pub fn parse_fields(fields: &[String], row: &[String]) -> Result<CdxInfo> {
+        if fields.len() != row.len() {
+            return Err(anyhow!("malformed response, should have {} elements {:?}", fields.len(), row));
+        }
+        let get = |name: &str| field(fields, row, name);
+        let (Some(original), Some(timestamp)) = (get("original"), get("timestamp")) else {
+            return Err(anyhow!("cdx rows need original and timestamp, got {:?}", fields));
+        };
+        Self::parse_row(
+            original,
+            timestamp,
+            get("statuscode").unwrap_or("-"),
+            get("mimetype").unwrap_or("-"),
+            get("digest").unwrap_or("-"),
+            get("length").unwrap_or("-"),
+        )
+    }
+    /// the timestamp the way wayback writes it, YYYYMMDDhhmmss.
+    pub fn timestamp(&self) -> String {
+        self.archived_at.format(DATE_FORMAT).to_string()
+    }
+    pub fn web_url(&self, on_site: bool) -> String {
+        self.web_url_from(WAYBACK_WEB_URL_BASE, on_site)
+    }
+    pub fn web_url_from(&self, base: &str, on_site: bool) -> String {
+        // https://archive.org/post/1010104/cdx-digest-not-accurately-capturing-duplicates
+        // To get unaltered content from wayback machine, simply add "id_" after the timestamp in the url!
+        let from_wb = if on_site { "id_" } else { "if_" };
+        format!("{}{}{}/{}", base, self.timestamp(), from_wb, self.original)
+    }
+}
+
+impl FromRow for CdxInfo {
+    fn from_row(fields: &[String], row: &[String]) -> Result<Self> {
+        Self::parse_fields(fields, row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::Page;
+
+    #[test]
+    fn parse_fields_any_order() {
+        let fields = ["length", "timestamp", "urlkey", "original"].map(String::from);
+        let row = ["414", "20150925144711", "com,davemolk)/", "http://davemolk.com/"].map(String::from);
+        let info = CdxInfo::parse_fields(&fields, &row).unwrap();
+        assert_eq!("http://davemolk.com/", info.original);
+        assert_eq!("20150925144711", info.timestamp());
+        assert_eq!(414, info.length);
+        assert_eq!(None, info.status_code);
+        assert_eq!("-", info.digest);
+        // can't do anything with a row that doesn't say what it is
+        assert!(CdxInfo::parse_fields(&fields[..2], &row[..2]).is_err());
+        assert!(CdxInfo::parse_fields(&fields, &row[..3]).is_err());
+    }
+    #[test]
+    fn parse_page_rows() {
+        let body = r#"[["original","timestamp","statuscode","mimetype","digest","length"],
+            ["http://davemolk.com/","20150925144711","301","unk","3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ","414"],
+            ["http://davemolk.com/","20160925144711","-","text/html","-","-"]]"#;
+        let rows = Page::parse(body).unwrap().parse_rows::<CdxInfo>().unwrap();
+        assert_eq!(2, rows.len());
+        assert_eq!(Some(301), rows[0].status_code);
+        assert_eq!(0, rows[1].length);
+    }
+    #[test]
+    fn web_url() {
+        let info = CdxInfo::parse_row("http://davemolk.com/", "20150925144711", "301", "unk", "3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ", "414").unwrap();
+        assert_eq!("http://web.archive.org/web/20150925144711id_/http://davemolk.com/", info.web_url(true));
+        assert_eq!("http://web.archive.org/web/20150925144711if_/http://davemolk.com/", info.web_url(false));
+    }
+}
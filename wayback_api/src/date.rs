@@ -0,0 +1,103 @@
+// wayback timestamps, YYYYMMDDhhmmss
+use anyhow::{anyhow, Result};
+use chrono::{Months, NaiveDateTime, TimeDelta};
+
+pub const DATE_FORMAT: &str = "%Y%m%d%H%M%S";
+
+/// a timestamp the way wayback takes them, YYYYMMDDhhmmss with any number of
+/// trailing digits left off, as the earliest moment it could mean.
+pub fn parse_timestamp(timestamp: &str) -> Result<NaiveDateTime> {
+    anyhow::ensure!(
+        !timestamp.is_empty() && timestamp.len() <= 14 && timestamp.chars().all(|c| c.is_ascii_digit()),
+        "timestamp format is YYYYMMDDhhmmss, got {}", timestamp,
+    );
+    let mut full = timestamp.to_owned();
+    // half a month or day: 0 can't be followed by 0, anything else can
+    if full.len() == 5 || full.len() == 7 {
+        full.push(if full.ends_with('0') { '1' } else { '0' });
+    }
+    full.push_str(&"00000101000000"[full.len()..]);
+    Ok(NaiveDateTime::parse_from_str(&full, DATE_FORMAT)?)
+}
+
+/// a stretch of time someone typed, from start up to (not including) end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Period {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+/// YYYY-MM-DD, YYYY-MM or YYYY as the day, month or year it means, or a wayback
+/// timestamp as the day, hour, minute and so on its digits get down to.
+pub fn parse_period(date: &str) -> Result<Period> {
+    let digits = if date.contains('-') {
+        let parts = date.split('-').collect::<Vec<_>>();
+        anyhow::ensure!(
+            parts.len() <= 3 && parts[0].len() == 4 && parts[1..].iter().all(|p| (1..=2).contains(&p.len())),
+            "date format is YYYY-MM-DD, YYYY-MM or YYYY, got {}", date,
+        );
+        parts.iter().map(|p| format!("{:0>2}", p)).collect::<String>()
+    } else {
+        date.to_owned()
+    };
+    anyhow::ensure!(
+        digits.len() >= 4 && digits.len() % 2 == 0,
+        "date format is YYYY-MM-DD, YYYY-MM or YYYY, got {}", date,
+    );
+    let start = parse_timestamp(&digits).map_err(|_| anyhow!("not a date: {}", date))?;
+    let end = match digits.len() {
+        4 => start.checked_add_months(Months::new(12)),
+        6 => start.checked_add_months(Months::new(1)),
+        8 => Some(start + TimeDelta::days(1)),
+        10 => Some(start + TimeDelta::hours(1)),
+        12 => Some(start + TimeDelta::minutes(1)),
+        _ => Some(start + TimeDelta::seconds(1)),
+    };
+    Ok(Period { start, end: end.ok_or_else(|| anyhow!("not a date: {}", date))? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!("20150101000000", parse_timestamp("2015").unwrap().format(DATE_FORMAT).to_string());
+        assert_eq!("20150925144711", parse_timestamp("20150925144711").unwrap().format(DATE_FORMAT).to_string());
+        // odd lengths are the first month, day and so on that starts with the digit
+        let padded = |s: &str| parse_timestamp(s).unwrap().format(DATE_FORMAT).to_string();
+        assert_eq!("20150501000000", padded("2015050"));
+        assert_eq!("20150101000000", padded("20150"));
+        assert_eq!("20151001000000", padded("20151"));
+        assert_eq!("20150130000000", padded("2015013"));
+        assert_eq!("20150930000000", padded("2015093"));
+        assert_eq!("20150910000000", padded("2015091"));
+        assert_eq!("20150925100000", padded("201509251"));
+        assert_eq!("20150925144000", padded("20150925144"));
+        assert_eq!("20150925144710", padded("2015092514471"));
+        assert!(parse_timestamp("20152").is_err());
+        assert!(parse_timestamp("2015023").is_err());
+        let expect = NaiveDate::from_ymd_opt(2024, 2, 28).unwrap().and_hms_opt(10, 55, 14).unwrap();
+        assert_eq!(expect, parse_timestamp("20240228105514").unwrap());
+        assert!(parse_timestamp("2015-09").is_err());
+        assert!(parse_timestamp("").is_err());
+    }
+    #[test]
+    fn test_parse_period() {
+        let at = |s: &str| parse_timestamp(s).unwrap();
+        assert_eq!(Period { start: at("20201031"), end: at("20201101") }, parse_period("2020-10-31").unwrap());
+        assert_eq!(Period { start: at("20200301"), end: at("20200302") }, parse_period("2020-3-1").unwrap());
+        assert_eq!(Period { start: at("202012"), end: at("2021") }, parse_period("2020-12").unwrap());
+        assert_eq!(Period { start: at("2020"), end: at("2021") }, parse_period("2020").unwrap());
+        assert_eq!(Period { start: at("20201031"), end: at("20201101") }, parse_period("20201031").unwrap());
+        assert_eq!(Period { start: at("2020103112"), end: at("2020103113") }, parse_period("2020103112").unwrap());
+        // day before month is what this used to want, it isn't a date now
+        assert!(parse_period("2020-31-10").is_err());
+        assert!(parse_period("2020-02-30").is_err());
+        assert!(parse_period("10/31/200").is_err());
+        assert!(parse_period("2020-10-31-1").is_err());
+        assert!(parse_period("20201").is_err());
+        assert!(parse_period("20").is_err());
+    }
+}
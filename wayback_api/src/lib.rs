@@ -0,0 +1,13 @@
+// what wayback_urls and wayback_downloads share for talking to the wayback machine:
+// getting responses (over http, or replayed from fixtures), the json tables cdx
+// and timemap answer with, typed rows for each, wayback's dates, and staying
+// under a rate limit
+pub mod cdx;
+pub mod date;
+pub mod limiter;
+pub mod paging;
+pub mod table;
+#[cfg(feature = "test_server")]
+pub mod test_server;
+pub mod timemap;
+pub mod transport;
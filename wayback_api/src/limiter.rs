@@ -0,0 +1,69 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// spaces requests out so that all the workers together stay under a
+/// requests per second budget. a Retry-After from the server pauses everyone.
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64) -> Self {
+        let interval = if per_second > 0.0 { Duration::from_secs_f64(1.0 / per_second) } else { Duration::ZERO };
+        RateLimiter { interval, next: Mutex::new(Instant::now()) }
+    }
+    /// blocks until it's this caller's turn to send a request.
+    pub fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        let now = Instant::now();
+        if slot > now {
+            thread::sleep(slot - now);
+        }
+    }
+    /// holds off every request until the given time.
+    pub fn pause_until(&self, until: Instant) {
+        let mut next = self.next.lock().unwrap();
+        if until > *next {
+            *next = until;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_rate_limiter_spaces_requests() {
+        let limiter = Arc::new(RateLimiter::new(50.0));
+        let start = Instant::now();
+        thread::scope(|s| {
+            for _ in 0..3 {
+                let limiter = Arc::clone(&limiter);
+                s.spawn(move || {
+                    for _ in 0..4 {
+                        limiter.wait();
+                    }
+                });
+            }
+        });
+        // 12 requests at 50/s, the first one goes right away
+        assert!(start.elapsed() >= Duration::from_millis(220), "{:?}", start.elapsed());
+    }
+    #[test]
+    fn test_rate_limiter_pause() {
+        let limiter = RateLimiter::new(0.0);
+        let start = Instant::now();
+        limiter.pause_until(start + Duration::from_millis(100));
+        limiter.wait();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
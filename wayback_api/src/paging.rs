@@ -0,0 +1,136 @@
+// big cdx and timemap queries a page at a time: limit rows per request, carrying
+// on from the resume key each page ends with
+use anyhow::Result;
+
+use crate::table::Page;
+use crate::transport::Transport;
+
+/// why there may be rows a query didn't get to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    /// stopped at the most rows asked for, with more to come.
+    Max(usize),
+    /// the first page came back full without a resume key, so paging isn't
+    /// happening and it was probably cut short.
+    Page(usize),
+}
+
+pub struct Pages<'a> {
+    transport: &'a dyn Transport,
+    url: String,
+    limit: usize,
+    max: Option<usize>,
+    remaining: Option<usize>,
+    // None once there's nothing left to ask for
+    next: Option<Option<String>>,
+    hit: Option<Limit>,
+}
+
+impl<'a> Pages<'a> {
+    /// url is the query without limit or resume key. max stops after that many
+    /// rows, however many pages that takes.
+    pub fn new(transport: &'a dyn Transport, url: &str, limit: usize, max: Option<usize>) -> Self {
+        Pages { transport, url: url.to_owned(), limit: limit.max(1), max, remaining: max, next: Some(None), hit: None }
+    }
+    /// set once the pages run out, if they ran out early.
+    pub fn limit_hit(&self) -> Option<Limit> {
+        self.hit
+    }
+    fn fetch(&mut self, resume_key: Option<String>) -> Result<Page> {
+        let mut url = format!("{}&limit={}&showResumeKey=true", self.url, self.limit);
+        let first = resume_key.is_none();
+        if let Some(key) = resume_key {
+            // handed back already encoded
+            url.push_str(&format!("&resumeKey={}", key));
+        }
+        let mut page = Page::parse(&self.transport.get(&url)?)?;
+        if first && page.resume_key.is_none() && page.rows.len() >= self.limit {
+            self.hit = Some(Limit::Page(self.limit));
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            let more = page.rows.len() > *remaining || page.resume_key.is_some();
+            page.rows.truncate(*remaining);
+            *remaining -= page.rows.len();
+            if *remaining == 0 {
+                if more {
+                    self.hit = self.max.map(Limit::Max);
+                }
+                page.resume_key = None;
+            }
+        }
+        self.next = page.resume_key.clone().map(Some);
+        Ok(page)
+    }
+}
+
+impl Iterator for Pages<'_> {
+    type Item = Result<Page>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let resume_key = self.next.take()?;
+        Some(self.fetch(resume_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Replay;
+
+    const HEADER: &str = r#"["original","timestamp"]"#;
+
+    fn row(n: usize) -> String {
+        format!(r#"["http://davemolk.com/{}","20150925144711"]"#, n)
+    }
+    // three pages of two rows, the last one without a key
+    fn replay() -> Replay {
+        Replay::new()
+            .fixture("resumeKey=b", &format!("[{},{},{}]", HEADER, row(5), row(6)))
+            .fixture("resumeKey=a", &format!("[{},{},{},[],[\"b\"]]", HEADER, row(3), row(4)))
+            .fixture("", &format!("[{},{},{},[],[\"a\"]]", HEADER, row(1), row(2)))
+    }
+    fn originals(pages: &mut Pages) -> Vec<String> {
+        pages.flat_map(|page| page.unwrap().rows).map(|row| row[0].clone()).collect()
+    }
+
+    #[test]
+    fn follows_resume_keys() {
+        let replay = replay();
+        let mut pages = Pages::new(&replay, "http://example.com/cdx?url=davemolk.com", 2, None);
+        assert_eq!(6, originals(&mut pages).len());
+        assert_eq!(None, pages.limit_hit());
+        assert_eq!(
+            vec![
+                "http://example.com/cdx?url=davemolk.com&limit=2&showResumeKey=true",
+                "http://example.com/cdx?url=davemolk.com&limit=2&showResumeKey=true&resumeKey=a",
+                "http://example.com/cdx?url=davemolk.com&limit=2&showResumeKey=true&resumeKey=b",
+            ],
+            replay.requests(),
+        );
+    }
+    #[test]
+    fn stops_at_max() {
+        let replay = replay();
+        let mut pages = Pages::new(&replay, "http://example.com/cdx?url=davemolk.com", 2, Some(3));
+        assert_eq!(vec!["http://davemolk.com/1", "http://davemolk.com/2", "http://davemolk.com/3"], originals(&mut pages));
+        assert_eq!(Some(Limit::Max(3)), pages.limit_hit());
+        assert_eq!(2, replay.requests().len());
+        // exactly everything there is isn't cut short
+        let mut pages = Pages::new(&replay, "http://example.com/cdx?url=davemolk.com", 2, Some(6));
+        assert_eq!(6, originals(&mut pages).len());
+        assert_eq!(None, pages.limit_hit());
+    }
+    #[test]
+    fn full_page_without_key() {
+        let replay = Replay::new().fixture("", &format!("[{},{},{}]", HEADER, row(1), row(2)));
+        let mut pages = Pages::new(&replay, "http://example.com/timemap?url=davemolk.com", 2, None);
+        assert_eq!(2, originals(&mut pages).len());
+        assert_eq!(Some(Limit::Page(2)), pages.limit_hit());
+    }
+    #[test]
+    fn stops_after_error() {
+        let replay = Replay::new();
+        let mut pages = Pages::new(&replay, "http://example.com/cdx?url=davemolk.com", 2, None);
+        assert!(pages.next().unwrap().is_err());
+        assert!(pages.next().is_none());
+    }
+}
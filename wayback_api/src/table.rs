@@ -0,0 +1,71 @@
+// cdx and timemap both answer with a json table: a header row naming the fields,
+// the rows, and with showResumeKey an empty row followed by the key for the next page
+use anyhow::Result;
+
+/// a row type that can be read out of a table, given its header row.
+pub trait FromRow: Sized {
+    fn from_row(fields: &[String], row: &[String]) -> Result<Self>;
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Page {
+    pub fields: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// where the next page starts, if there is one.
+    pub resume_key: Option<String>,
+}
+
+impl Page {
+    pub fn parse(body: &str) -> Result<Page> {
+        // no results at all comes back as an empty body rather than []
+        if body.trim().is_empty() {
+            return Ok(Page::default());
+        }
+        let table: Vec<Vec<String>> = serde_json::from_str(body)?;
+        let mut rows = table.into_iter();
+        let mut page = Page { fields: rows.next().unwrap_or_default(), ..Default::default() };
+        for row in rows.by_ref() {
+            if row.is_empty() {
+                break;
+            }
+            page.rows.push(row);
+        }
+        page.resume_key = rows.next().and_then(|row| row.into_iter().next()).filter(|key| !key.is_empty());
+        Ok(page)
+    }
+    /// every row as a T, failing on the first one that isn't.
+    pub fn parse_rows<T: FromRow>(&self) -> Result<Vec<T>> {
+        self.rows.iter().map(|row| T::from_row(&self.fields, row)).collect()
+    }
+}
+
+/// the value of a named field in row, if the header has it.
+pub fn field<'a>(fields: &[String], row: &'a [String], name: &str) -> Option<&'a str> {
+    fields.iter().position(|f| f == name).and_then(|i| row.get(i)).map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"["original","timestamp","statuscode","mimetype","digest","length"]"#;
+    const ROW: &str = r#"["http://davemolk.com/","20150925144711","200","text/html","3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ","414"]"#;
+
+    #[test]
+    fn parse_page_with_resume_key() {
+        let page = Page::parse(&format!("[{},{},[],[\"com%2Cdavemolk%29%2F+20150925144711\"]]", HEADER, ROW)).unwrap();
+        assert_eq!(6, page.fields.len());
+        assert_eq!(1, page.rows.len());
+        assert_eq!(Some("com%2Cdavemolk%29%2F+20150925144711".to_string()), page.resume_key);
+        assert_eq!(Some("414"), field(&page.fields, &page.rows[0], "length"));
+        assert_eq!(None, field(&page.fields, &page.rows[0], "urlkey"));
+    }
+    #[test]
+    fn parse_page_empty() {
+        assert_eq!(Page::default(), Page::parse("").unwrap());
+        assert_eq!(Page::default(), Page::parse("[]").unwrap());
+        let page = Page::parse(&format!("[{},{}]", HEADER, ROW)).unwrap();
+        assert_eq!(None, page.resume_key);
+        assert!(Page::parse("<html>").is_err());
+    }
+}
@@ -0,0 +1,106 @@
+// a tiny http server for tests, so nothing has to talk to archive.org
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    // close the connection after this much of the body
+    pub cut_after: Option<usize>,
+}
+
+impl Response {
+    pub fn ok(body: &[u8]) -> Self {
+        Response { status: 200, headers: Vec::new(), body: body.to_vec(), cut_after: None }
+    }
+    pub fn status(status: u16) -> Self {
+        Response { status, headers: Vec::new(), body: Vec::new(), cut_after: None }
+    }
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    /// says how long the body is, then hangs up partway through it.
+    pub fn cut_after(mut self, bytes: usize) -> Self {
+        self.cut_after = Some(bytes);
+        self
+    }
+}
+
+pub struct TestServer {
+    pub base: String,
+    // every path that was requested, in order
+    pub requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    /// serves requests with the handler until the test process exits.
+    /// the handler gets the path (with query string) of each request.
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str) -> Response + Send + Sync + 'static,
+    {
+        TestServer::start_with_method(move |_, path| handler(path))
+    }
+    /// start, with the handler getting the method too.
+    pub fn start_with_method<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &str) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test server");
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let handler = Arc::clone(&handler);
+                let seen = Arc::clone(&seen);
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).is_err() {
+                        return;
+                    }
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap_or(0);
+                            }
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    let _ = reader.read_exact(&mut body);
+                    let mut parts = request_line.split_whitespace();
+                    let method = parts.next().unwrap_or("").to_string();
+                    let path = parts.next().unwrap_or("/").to_string();
+                    seen.lock().unwrap().push(path.clone());
+                    let resp = handler(&method, &path);
+                    let mut out = format!("HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n", resp.status, resp.body.len());
+                    for (name, value) in &resp.headers {
+                        out.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    out.push_str("\r\n");
+                    let _ = stream.write_all(out.as_bytes());
+                    if method != "HEAD" {
+                        let end = resp.cut_after.unwrap_or(resp.body.len()).min(resp.body.len());
+                        let _ = stream.write_all(&resp.body[..end]);
+                    }
+                });
+            }
+        });
+        TestServer { base, requests }
+    }
+    pub fn hits(&self, path: &str) -> usize {
+        self.requests.lock().unwrap().iter().filter(|p| p.as_str() == path).count()
+    }
+}
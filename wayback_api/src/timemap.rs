@@ -0,0 +1,81 @@
+// rows from the timemap endpoint, one per url with the range it was captured over
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+
+use crate::date::parse_timestamp;
+use crate::table::{field, FromRow};
+
+/// what wayback_urls asks the timemap for.
+pub const TIMEMAP_FIELDS: &[&str] = &["original", "mimetype", "timestamp", "endtimestamp", "groupcount", "uniqcount"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimemapRow {
+    pub original: String,
+    pub mime_type: String,
+    /// first capture, None if wayback didn't give a usable timestamp.
+    pub from: Option<NaiveDateTime>,
+    /// last capture.
+    pub to: Option<NaiveDateTime>,
+    /// how many captures there are.
+    pub group_count: u64,
+    /// how many of those differ in content.
+    pub uniq_count: u64,
+}
+
+impl FromRow for TimemapRow {
+    /// only original has to be there, anything else missing is left unknown.
+    fn from_row(fields: &[String], row: &[String]) -> Result<Self> {
+        if fields.len() != row.len() {
+            return Err(anyhow!("malformed response, should have {} elements {:?}", fields.len(), row));
+        }
+        let get = |name: &str| field(fields, row, name).unwrap_or("-");
+        let Some(original) = field(fields, row, "original") else {
+            return Err(anyhow!("timemap rows need original, got {:?}", fields));
+        };
+        let count = |name: &str| -> Result<u64> {
+            match get(name) {
+                "-" => Ok(0),
+                n => n.parse().map_err(|_| anyhow!("bad {} {:?}", name, n)),
+            }
+        };
+        Ok(TimemapRow {
+            original: original.to_owned(),
+            mime_type: get("mimetype").to_owned(),
+            from: parse_timestamp(get("timestamp")).ok(),
+            to: parse_timestamp(get("endtimestamp")).ok(),
+            group_count: count("groupcount")?,
+            uniq_count: count("uniqcount")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::Page;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn parse_timemap_rows() {
+        let body = r#"[["original","mimetype","timestamp","endtimestamp","groupcount","uniqcount"],
+            ["http://rust-lang.org/","text/html","20110904062405","20240512092335","7645","593"],
+            ["https://rust-lang.org/%22,","unk","-","-","1","1"]]"#;
+        let rows = Page::parse(body).unwrap().parse_rows::<TimemapRow>().unwrap();
+        assert_eq!(2, rows.len());
+        assert_eq!("http://rust-lang.org/", rows[0].original);
+        assert_eq!(Some(NaiveDate::from_ymd_opt(2011, 9, 4).unwrap().and_hms_opt(6, 24, 5).unwrap()), rows[0].from);
+        assert_eq!(7645, rows[0].group_count);
+        assert_eq!(593, rows[0].uniq_count);
+        assert_eq!(None, rows[1].from);
+        assert_eq!("unk", rows[1].mime_type);
+    }
+    #[test]
+    fn parse_timemap_fields() {
+        let fields = ["original", "groupcount"].map(String::from);
+        let row = TimemapRow::from_row(&fields, &["http://rust-lang.org/".to_owned(), "3".to_owned()]).unwrap();
+        assert_eq!(3, row.group_count);
+        assert_eq!("-", row.mime_type);
+        assert!(TimemapRow::from_row(&fields, &["http://rust-lang.org/".to_owned()]).is_err());
+        assert!(TimemapRow::from_row(&fields, &["http://rust-lang.org/".to_owned(), "x".to_owned()]).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+// how api requests get answered: over http, or from canned responses so tests
+// (and anyone without a network) can replay recorded ones
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::limiter::RateLimiter;
+
+pub const USER_AGENT: &str = "https://github.com/davemolk/rust-scripts";
+
+/// gets the body of a wayback api url. shared between threads.
+pub trait Transport: Send + Sync {
+    fn get(&self, url: &str) -> Result<String>;
+}
+
+pub struct HttpTransport {
+    client: reqwest::blocking::Client,
+}
+
+impl HttpTransport {
+    pub fn new() -> Self {
+        HttpTransport { client: reqwest::blocking::Client::new() }
+    }
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for HttpTransport {
+    fn get(&self, url: &str) -> Result<String> {
+        let resp = self.client
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .send()?;
+        match resp.status() {
+            reqwest::StatusCode::OK => Ok(resp.text()?),
+            status => Err(anyhow!("unexpected response status {}", status)),
+        }
+    }
+}
+
+/// another transport, with every request (from any thread) counted against
+/// one requests per second budget.
+pub struct Throttled {
+    inner: Arc<dyn Transport>,
+    limiter: RateLimiter,
+}
+
+impl Throttled {
+    /// 0 for no limit.
+    pub fn new(inner: Arc<dyn Transport>, per_second: f64) -> Self {
+        Throttled { inner, limiter: RateLimiter::new(per_second) }
+    }
+}
+
+impl Transport for Throttled {
+    fn get(&self, url: &str) -> Result<String> {
+        self.limiter.wait();
+        self.inner.get(url)
+    }
+}
+
+/// answers from fixtures instead of the network, and keeps track of what was asked.
+#[derive(Default)]
+pub struct Replay {
+    fixtures: Vec<(String, String)>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl Replay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// body for any url containing pattern. the first fixture that matches wins,
+    /// and a url nothing matches is an error.
+    pub fn fixture(mut self, pattern: &str, body: &str) -> Self {
+        self.fixtures.push((pattern.to_owned(), body.to_owned()));
+        self
+    }
+    /// like fixture, with the body read from a file.
+    pub fn fixture_file(self, pattern: &str, path: impl AsRef<Path>) -> Result<Self> {
+        let body = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| anyhow!("failed to read fixture {}: {}", path.as_ref().display(), e))?;
+        Ok(self.fixture(pattern, &body))
+    }
+    /// every url asked for so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for Replay {
+    fn get(&self, url: &str) -> Result<String> {
+        self.requests.lock().unwrap().push(url.to_owned());
+        self.fixtures
+            .iter()
+            .find(|(pattern, _)| url.contains(pattern.as_str()))
+            .map(|(_, body)| body.clone())
+            .ok_or_else(|| anyhow!("no fixture for {}", url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let replay = Replay::new().fixture("page=1", "second").fixture("", "first");
+        assert_eq!("second", replay.get("http://example.com/cdx?url=a&page=1").unwrap());
+        assert_eq!("first", replay.get("http://example.com/cdx?url=a").unwrap());
+        assert_eq!(vec!["http://example.com/cdx?url=a&page=1", "http://example.com/cdx?url=a"], replay.requests());
+        let none = Replay::new().fixture("timemap", "[]");
+        assert!(none.get("http://example.com/cdx").unwrap_err().to_string().contains("no fixture"));
+        assert!(Replay::new().fixture_file("", "does/not/exist.json").is_err());
+    }
+    #[test]
+    fn test_throttled() {
+        let replay = Arc::new(Replay::new().fixture("timemap", "[]").fixture("cdx", "[[]]"));
+        let throttled = Throttled::new(replay.clone(), 0.0);
+        assert_eq!("[[]]", throttled.get("http://example.com/cdx?url=a").unwrap());
+        assert_eq!("[]", throttled.get("http://example.com/timemap?url=a").unwrap());
+        let err = throttled.get("http://example.com/other").unwrap_err();
+        assert!(err.to_string().contains("no fixture"));
+        assert_eq!(
+            vec!["http://example.com/cdx?url=a", "http://example.com/timemap?url=a", "http://example.com/other"],
+            replay.requests(),
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// what archive.org already told us, kept on disk so asking again is free
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cdx::CdxInfo;
+use crate::download::{digest, is_digest};
+
+/// cdx responses are kept by a hash of the request url, raw snapshots by their
+/// cdx digest, so several jobs (or people) can share one directory. snapshots as
+/// wayback serves them have links with their own timestamp in them, so those go
+/// by capture, as does anything without a digest.
+///
+/// <dir>/cdx/<sha1 of url>.json
+/// <dir>/snapshots/id_/<digest>                                raw
+/// <dir>/snapshots/{id_,if_}/captures/<sha1 of timestamp url>   by capture
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    offline: bool,
+}
+
+impl Cache {
+    pub fn new(dir: &Path) -> Self {
+        Cache { dir: dir.to_owned(), offline: false }
+    }
+    /// only ever answer from the cache, anything missing is an error.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+    pub fn cdx(&self, url: &str) -> Option<String> {
+        fs::read_to_string(self.cdx_path(url)).ok()
+    }
+    pub fn put_cdx(&self, url: &str, body: &str) -> Result<()> {
+        write(&self.cdx_path(url), body.as_bytes())
+    }
+    pub fn snapshot(&self, info: &CdxInfo, raw: bool) -> Option<Vec<u8>> {
+        fs::read(self.snapshot_path(info, raw)).ok()
+    }
+    pub fn put_snapshot(&self, info: &CdxInfo, raw: bool, body: &[u8]) -> Result<()> {
+        write(&self.snapshot_path(info, raw), body)
+    }
+    /// the error for something an offline cache doesn't have.
+    pub fn missing(&self, what: &str) -> anyhow::Error {
+        anyhow!("{} isn't in the cache at {} (offline)", what, self.dir.display())
+    }
+    fn cdx_path(&self, url: &str) -> PathBuf {
+        self.dir.join("cdx").join(format!("{}.json", digest(url.as_bytes())))
+    }
+    fn snapshot_path(&self, info: &CdxInfo, raw: bool) -> PathBuf {
+        let dir = self.dir.join("snapshots").join(if raw { "id_" } else { "if_" });
+        if raw && is_digest(&info.digest) {
+            return dir.join(&info.digest);
+        }
+        let capture = format!("{} {}", info.timestamp(), info.original);
+        dir.join("captures").join(digest(capture.as_bytes()))
+    }
+}
+
+// written to a temp file and renamed, so someone else reading the same cache
+// never sees half a file
+fn write(path: &Path, data: &[u8]) -> Result<()> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension(format!("{}.{}.tmp", std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst)));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("wayback_cache_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = Cache::new(&dir);
+        assert_eq!(None, cache.cdx("http://example.com/cdx?url=a"));
+        cache.put_cdx("http://example.com/cdx?url=a", "[]").unwrap();
+        assert_eq!(Some("[]".to_string()), cache.cdx("http://example.com/cdx?url=a"));
+        assert_eq!(None, cache.cdx("http://example.com/cdx?url=b"));
+
+        let digest = "3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ";
+        let info = |original: &str, timestamp: &str, digest: &str| CdxInfo::parse_row(original, timestamp, "200", "text/html", digest, "3").unwrap();
+        let first = info("http://davemolk.com/", "20150925144711", digest);
+        let later = info("http://davemolk.com/", "20170101000000", digest);
+        cache.put_snapshot(&first, true, b"raw").unwrap();
+        assert_eq!(Some(b"raw".to_vec()), cache.snapshot(&first, true));
+        // raw is the same for anything with the digest
+        assert_eq!(Some(b"raw".to_vec()), cache.snapshot(&later, true));
+        assert_eq!(None, cache.snapshot(&first, false));
+        assert!(dir.join("snapshots/id_").join(digest).exists());
+        // rewritten isn't, its links have the capture's timestamp
+        cache.put_snapshot(&first, false, b"2015 links").unwrap();
+        assert_eq!(Some(b"2015 links".to_vec()), cache.snapshot(&first, false));
+        assert_eq!(None, cache.snapshot(&later, false));
+        // unknown digests go by capture too
+        let unknown = info("http://davemolk.com/", "20150925144711", "-");
+        cache.put_snapshot(&unknown, true, b"raw, no digest").unwrap();
+        assert_eq!(Some(b"raw, no digest".to_vec()), cache.snapshot(&unknown, true));
+        assert_eq!(None, cache.snapshot(&info("http://davemolk.com/about", "20150925144711", "-"), true));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
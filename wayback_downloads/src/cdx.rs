@@ -1,134 +1,317 @@
 use anyhow::{anyhow, Result};
-use reqwest;
-use chrono::NaiveDateTime;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use wayback_api::table::Page;
+use wayback_api::transport::{HttpTransport, Transport};
+
+use crate::cache::Cache;
+
+pub use wayback_api::cdx::{CdxInfo, WAYBACK_WEB_URL_BASE};
+pub use wayback_api::date::{parse_timestamp, DATE_FORMAT};
 
 const CDX_BASE: &str = "http://web.archive.org/cdx/search/cdx?url=";
 const CDX_PARAMS: &str = "&output=json&fl=original,timestamp,statuscode,mimetype,digest,length";
-const DATE_FORMAT: &str = "%Y%m%d%H%M%S";
-const WAYBACK_WEB_URL_BASE: &str = "http://web.archive.org/web/";
-#[derive(Clone, Debug)]
-pub struct CdxInfo {
-    original: String,
-    archived_at: NaiveDateTime,
-    status_code: Option<u16>,
-    mime_type: String,
-    digest: String,
-    length: u64,
-}
-
-impl CdxInfo {
-    fn parse_row(
-        original: &str,
-        timestamp: &str,
-        status_code: &str,
-        mime_type: &str,
-        digest: &str,
-        length: &str,
-    ) -> Result<CdxInfo> {
-        let archived_at = NaiveDateTime::parse_from_str(&timestamp, DATE_FORMAT)?;
-        let length = length.parse::<u64>()?;
-        // wb uses "-" for unknown/null values
-        let status_code = if status_code == "-" { None } else { 
-            Some(status_code.parse::<u16>()?)
-        };
-        Ok(CdxInfo{
-            original: original.to_string(),
-            archived_at,
-            status_code,
-            mime_type: mime_type.to_string(),
-            digest: digest.to_string(),
-            length,
-        })
+// everything cdx can hand back per row
+pub const CDX_FIELDS: &[&str] = &[
+    "urlkey", "timestamp", "original", "mimetype", "statuscode", "digest",
+    "redirect", "robotflags", "length", "offset", "filename",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchType {
+    Exact,
+    Prefix,
+    Host,
+    Domain,
+}
+
+impl MatchType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MatchType::Exact => "exact",
+            MatchType::Prefix => "prefix",
+            MatchType::Host => "host",
+            MatchType::Domain => "domain",
+        }
     }
-    fn web_url(&self, on_site: bool) -> String {
-        // https://archive.org/post/1010104/cdx-digest-not-accurately-capturing-duplicates
-        // To get unaltered content from wayback machine, simply add "id_" after the timestamp in the url!
-        let from_wb = if on_site { "id_" } else { "if_" };
-        format!("{}{}{}/{}", WAYBACK_WEB_URL_BASE, self.archived_at, from_wb, self.original)
+}
+
+/// everything that goes into a cdx request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CdxQuery {
+    pub url: String,
+    // collapse=digest
+    pub unique: bool,
+    pub from_date: Option<String>,
+    pub to_date: Option<String>,
+    pub limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub match_type: Option<MatchType>,
+    /// field:regex, or !field:regex for rows that don't match.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<String>,
+    /// field, or field:N to only compare the first N characters.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collapse: Vec<String>,
+    /// the fl list, empty for the default of original,timestamp,statuscode,mimetype,digest,length.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+impl CdxQuery {
+    pub fn new(url: &str) -> Self {
+        CdxQuery { url: url.to_owned(), ..Default::default() }
+    }
+}
+
+/// checks a --filter, [!]field:regex.
+pub fn validate_filter(arg: &str) -> Result<String> {
+    let (field, regex) = arg.trim_start_matches('!').split_once(':')
+        .ok_or_else(|| anyhow!("format is field:regex, or !field:regex to exclude"))?;
+    validate_field(field)?;
+    anyhow::ensure!(!regex.is_empty(), "missing regex after {}:", field);
+    Ok(arg.to_owned())
+}
+
+/// checks a --collapse, field or field:N.
+pub fn validate_collapse(arg: &str) -> Result<String> {
+    let (field, n) = arg.split_once(':').unwrap_or((arg, "1"));
+    validate_field(field)?;
+    anyhow::ensure!(!n.is_empty() && n.chars().all(|c| c.is_ascii_digit()), "format is field or field:N");
+    Ok(arg.to_owned())
+}
+
+pub fn validate_field(field: &str) -> Result<String> {
+    anyhow::ensure!(CDX_FIELDS.contains(&field), "unknown cdx field {}, should be one of {}", field, CDX_FIELDS.join(", "));
+    Ok(field.to_owned())
+}
+
+// percent-encodes what would otherwise break up the query string,
+// leaving regex punctuation readable
+fn encode(value: &str) -> String {
+    let mut out = String::new();
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'!' | b'*' | b'\'' | b'(' | b')'
+                | b':' | b'/' | b',' | b'[' | b']' | b'^' | b'$' | b'|' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
     }
+    out
 }
 
 pub struct CdxClient {
-    client: reqwest::blocking::Client,
+    transport: Arc<dyn Transport>,
+    base: String,
+    paging: Paging,
+    max_results: Option<usize>,
+    cache: Option<Cache>,
 }
 
-type CdxResponse = Vec<Vec<String>>;
+// rows per request when following resume keys and no --page-size was given
+const DEFAULT_PAGE_SIZE: usize = 5000;
 
+/// how to split a big query into several requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Paging {
+    /// limit rows per request, carrying on from the resume key cdx hands back.
+    ResumeKey,
+    /// ask how many pages there are (showNumPages), then fetch them one at a time.
+    /// pages are index blocks, so their size is up to cdx.
+    Pages,
+}
 
 impl CdxClient {
-    fn new() -> Self {
+    pub fn new() -> Self {
+        CdxClient::with_base(CDX_BASE)
+    }
+    /// points the client somewhere other than web.archive.org, mostly for tests.
+    /// base should end with "?url=".
+    pub fn with_base(base: &str) -> Self {
         CdxClient{
-            client: reqwest::blocking::Client::new(),
+            transport: Arc::new(HttpTransport::new()),
+            base: base.to_owned(),
+            paging: Paging::ResumeKey,
+            max_results: None,
+            cache: None,
         }
     }
-    fn get_query_url(&self, query: &str, unique: bool, from_date: Option<String>, to_date: Option<String>, limit: Option<usize>) -> String {
-        let mut query_url = format!("{}{}{}", CDX_BASE, query, CDX_PARAMS);
-        if unique {
+    /// how requests get answered, over http unless told otherwise.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+    /// answer from (and save responses to) an on-disk cache.
+    pub fn cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+    pub fn paging(mut self, paging: Paging) -> Self {
+        self.paging = paging;
+        self
+    }
+    /// stop after this many rows, however many pages that takes.
+    pub fn max_results(mut self, max: Option<usize>) -> Self {
+        self.max_results = max;
+        self
+    }
+    fn get_query_url(&self, query: &CdxQuery) -> String {
+        let mut query_url = format!("{}{}", self.base, query.url);
+        if query.fields.is_empty() {
+            query_url.push_str(CDX_PARAMS);
+        } else {
+            query_url.push_str(&format!("&output=json&fl={}", query.fields.join(",")));
+        }
+        if let Some(match_type) = query.match_type {
+            query_url.push_str(&format!("&matchType={}", match_type.as_str()));
+        }
+        if query.unique {
             query_url.push_str("&collapse=digest");
         }
-        if let Some(from) = from_date {
+        for collapse in query.collapse.iter().filter(|c| !(query.unique && c.as_str() == "digest")) {
+            query_url.push_str(&format!("&collapse={}", encode(collapse)));
+        }
+        for filter in &query.filters {
+            query_url.push_str(&format!("&filter={}", encode(filter)));
+        }
+        if let Some(from) = &query.from_date {
             query_url.push_str(&format!("&from={}", from));
         }
-        if let Some(to) = to_date {
+        if let Some(to) = &query.to_date {
             query_url.push_str(&format!("&to={}", to));
         }
-        if let Some(l) = limit {
+        if let Some(l) = query.limit {
             query_url.push_str(&format!("&limit={}", l));
         }
         query_url
     }
-    fn search(&self, url: &str) -> Result<CdxResponse> {
-        let resp = self.client
-            .get(url)
-            .header(reqwest::header::ACCEPT,  "application/json")
-            .header(reqwest::header::USER_AGENT, "https://github.com/davemolk/rust-scripts")
-            .send()?;
-        match resp.status() {
-            reqwest::StatusCode::OK => {
-                let decoded = resp.json::<CdxResponse>()?;
-                return Ok(decoded)
-            },
-            _ => {
-                return Err(anyhow!("unexpected response status {}", resp.status()))
-            },
+    fn get(&self, url: &str) -> Result<String> {
+        if let Some(cache) = &self.cache {
+            if let Some(body) = cache.cdx(url) {
+                return Ok(body);
+            }
+            if cache.is_offline() {
+                return Err(cache.missing(url));
+            }
+        }
+        let body = self.transport.get(url)?;
+        if let Some(cache) = &self.cache {
+            cache.put_cdx(url, &body)?;
         }
+        Ok(body)
     }
-    fn parse_json(json: CdxResponse) -> Result<Vec<CdxInfo>> {
-        for row in &json {
-            if row.len() != 6 {
-                return Err(anyhow!("malformed response, should have 6 elements {:?}", row));
-            }
+    fn parse_page(body: &str) -> Result<(Vec<CdxInfo>, Option<String>)> {
+        let page = Page::parse(body)?;
+        Ok((page.parse_rows()?, page.resume_key))
+    }
+    /// every matching snapshot, fetched a page at a time as the iterator gets to it.
+    pub fn iter(&self, query: &CdxQuery) -> CdxIter<'_> {
+        let mut query = query.clone();
+        if self.paging == Paging::ResumeKey {
+            query.limit = Some(query.limit.unwrap_or(DEFAULT_PAGE_SIZE));
         }
-        let mut out: Vec<CdxInfo> = Vec::new();
-        // first row in response is the key (unless response is empty, which we checked above)
-        // order is original, timestamp, statuscode, mimetype, digest, length (from CDX_PARAMS)
-        for row in &json[1..] {
-            let parsed = CdxInfo::parse_row(&row[0], &row[1], &row[2], &row[3], &row[4], &row[5])?;
-            out.push(parsed);
+        CdxIter {
+            client: self,
+            query_url: self.get_query_url(&query),
+            next: Next::Start,
+            buffer: VecDeque::new(),
+            remaining: self.max_results,
         }
-        Ok(out)
     }
-    pub fn get_cdx(query: &str, unique: bool, from_date: Option<String>, to_date: Option<String>, limit: Option<usize>) -> Result<Vec<CdxInfo>> {
-        let client = CdxClient::new();
-        let query_url: String = client.get_query_url(query, unique, from_date, to_date, limit);
-        let res = client.search(&query_url)?;
-        if res.is_empty() {
-            return Err(anyhow!("no response for cdx"))
+    /// every matching snapshot at once, for when they all have to be looked at anyway.
+    pub fn get_cdx(&self, query: &CdxQuery) -> Result<Vec<CdxInfo>> {
+        self.iter(query).collect()
+    }
+}
+
+impl Default for CdxClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the request an iterator makes once it runs out of rows
+enum Next {
+    Start,
+    ResumeKey(String),
+    Page { page: u32, pages: u32 },
+    Done,
+}
+
+/// rows from a cdx query, see CdxClient::iter.
+pub struct CdxIter<'a> {
+    client: &'a CdxClient,
+    query_url: String,
+    next: Next,
+    buffer: VecDeque<CdxInfo>,
+    remaining: Option<usize>,
+}
+
+impl CdxIter<'_> {
+    fn fetch_next(&mut self) -> Result<()> {
+        let url = match (&self.next, self.client.paging) {
+            (Next::Done, _) => return Ok(()),
+            (Next::Start, Paging::ResumeKey) => format!("{}&showResumeKey=true", self.query_url),
+            // cdx hands the key back already encoded
+            (Next::ResumeKey(key), _) => format!("{}&showResumeKey=true&resumeKey={}", self.query_url, key),
+            (Next::Start, Paging::Pages) => {
+                let body = self.client.get(&format!("{}&showNumPages=true", self.query_url))?;
+                let pages = body.trim().parse::<u32>()
+                    .map_err(|_| anyhow!("unexpected showNumPages response {:?}", body))?;
+                self.next = if pages == 0 { Next::Done } else { Next::Page { page: 0, pages } };
+                return Ok(());
+            },
+            (Next::Page { page, .. }, _) => format!("{}&page={}", self.query_url, page),
+        };
+        let (rows, resume_key) = CdxClient::parse_page(&self.client.get(&url)?)?;
+        self.buffer.extend(rows);
+        self.next = match (&self.next, resume_key) {
+            (Next::Page { page, pages }, _) if page + 1 < *pages => Next::Page { page: page + 1, pages: *pages },
+            (Next::Page { .. }, _) => Next::Done,
+            (_, Some(key)) if !key.is_empty() => Next::ResumeKey(key),
+            _ => Next::Done,
+        };
+        Ok(())
+    }
+}
+
+impl Iterator for CdxIter<'_> {
+    type Item = Result<CdxInfo>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        while self.buffer.is_empty() {
+            if matches!(self.next, Next::Done) {
+                return None;
+            }
+            if let Err(e) = self.fetch_next() {
+                // don't keep hammering cdx after a failure
+                self.next = Next::Done;
+                return Some(Err(e));
+            }
+        }
+        if let Some(n) = self.remaining.as_mut() {
+            *n -= 1;
         }
-        let parsed = Self::parse_json(res)?;
-        Ok(parsed)
+        self.buffer.pop_front().map(Ok)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use wayback_api::test_server::{Response, TestServer};
+    use std::fs;
+    use wayback_api::transport::Replay;
     #[test]
     fn get_query_url_basic() {
         let query = "foo";
         let want = format!("{}{}{}", CDX_BASE, query, CDX_PARAMS);
         let client = CdxClient::new();
-        let got = client.get_query_url(query, false, None, None, None);
+        let got = client.get_query_url(&CdxQuery::new(query));
         assert_eq!(got, want);
     }
     #[test]
@@ -136,7 +319,7 @@ mod tests {
         let query = "foo";
         let want = format!("{}{}{}&collapse=digest", CDX_BASE, query, CDX_PARAMS);
         let client = CdxClient::new();
-        let got = client.get_query_url(query, true, None, None, None);
+        let got = client.get_query_url(&CdxQuery { unique: true, ..CdxQuery::new(query) });
         assert_eq!(got, want);
     }
     #[test]
@@ -145,7 +328,7 @@ mod tests {
         let from = "2022".to_owned();
         let want = format!("{}{}{}&from={}", CDX_BASE, query, CDX_PARAMS, from);
         let client = CdxClient::new();
-        let got = client.get_query_url(query, false, Some(from), None, None);
+        let got = client.get_query_url(&CdxQuery { from_date: Some(from), ..CdxQuery::new(query) });
         assert_eq!(got, want);
     }
     #[test]
@@ -154,7 +337,7 @@ mod tests {
         let to = "2022".to_owned();
         let want = format!("{}{}{}&to={}", CDX_BASE, query, CDX_PARAMS, to);
         let client = CdxClient::new();
-        let got = client.get_query_url(query, false, None, Some(to), None);
+        let got = client.get_query_url(&CdxQuery { to_date: Some(to), ..CdxQuery::new(query) });
         assert_eq!(got, want);
     }
     #[test]
@@ -163,7 +346,7 @@ mod tests {
         let limit = 5;
         let want = format!("{}{}{}&limit={}", CDX_BASE, query, CDX_PARAMS, limit);
         let client = CdxClient::new();
-        let got = client.get_query_url(query, false, None, None, Some(limit));
+        let got = client.get_query_url(&CdxQuery { limit: Some(limit), ..CdxQuery::new(query) });
         assert_eq!(got, want);
     }
     #[test]
@@ -175,16 +358,150 @@ mod tests {
         let limit = 5;
         let want = format!("{}{}{}&collapse=digest&from={}&to={}&limit={}", CDX_BASE, query, CDX_PARAMS, from, to, limit);
         let client = CdxClient::new();
-        let got = client.get_query_url(query, unique, Some(from), Some(to), Some(limit));
+        let got = client.get_query_url(&CdxQuery { unique, from_date: Some(from), to_date: Some(to), limit: Some(limit), ..CdxQuery::new(query) });
         assert_eq!(got, want);
     }
     #[test]
+    fn get_query_url_filters() {
+        let query = CdxQuery {
+            match_type: Some(MatchType::Prefix),
+            unique: true,
+            collapse: vec!["digest".to_owned(), "urlkey:20".to_owned()],
+            filters: vec!["statuscode:200".to_owned(), "!mimetype:image/.*".to_owned(), "original:.*\\?a=1&b".to_owned()],
+            fields: vec!["timestamp".to_owned(), "original".to_owned()],
+            ..CdxQuery::new("foo")
+        };
+        let want = format!(
+            "{}foo&output=json&fl=timestamp,original&matchType=prefix&collapse=digest&collapse=urlkey:20\
+             &filter=statuscode:200&filter=!mimetype:image/.*&filter=original:.*%5C%3Fa%3D1%26b",
+            CDX_BASE,
+        );
+        assert_eq!(want, CdxClient::new().get_query_url(&query));
+    }
+    #[test]
+    fn validate_args() {
+        assert!(validate_filter("statuscode:200").is_ok());
+        assert!(validate_filter("!mimetype:text/html").is_ok());
+        assert!(validate_filter("statuscode").is_err());
+        assert!(validate_filter("status:200").is_err());
+        assert!(validate_filter("statuscode:").is_err());
+        assert!(validate_collapse("digest").is_ok());
+        assert!(validate_collapse("urlkey:10").is_ok());
+        assert!(validate_collapse("urlkey:x").is_err());
+        assert!(validate_collapse("nope").is_err());
+    }
+    #[test]
     fn parse_json() {
         let file = std::fs::read_to_string("tests/cdx.json").unwrap();
-        let json = serde_json::from_str(&file).unwrap();
-        let res = CdxClient::parse_json(json).unwrap();
+        let (res, resume_key) = CdxClient::parse_page(&file).unwrap();
         // confirm the key is dropped
         assert_eq!(20, res.len());
+        assert_eq!(None, resume_key);
         assert_eq!("http://davemolk.com/".to_owned(), res[0].original);
     }
-}
\ No newline at end of file
+    fn row(timestamp: &str) -> String {
+        format!(r#"["http://davemolk.com/","{}","200","text/html","3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ","414"]"#, timestamp)
+    }
+    const HEADER: &str = r#"["original","timestamp","statuscode","mimetype","digest","length"]"#;
+
+    #[test]
+    fn iter_follows_resume_keys() {
+        let server = TestServer::start(|path| {
+            let body = if path.contains("resumeKey=two") {
+                format!("[{},{},[],[\"three\"]]", HEADER, row("20160101000000"))
+            } else if path.contains("resumeKey=three") {
+                format!("[{},{}]", HEADER, row("20170101000000"))
+            } else {
+                format!("[{},{},{},[],[\"two\"]]", HEADER, row("20140101000000"), row("20150101000000"))
+            };
+            Response::ok(body.as_bytes())
+        });
+        let client = CdxClient::with_base(&format!("{}/cdx?url=", server.base));
+        let got = client.iter(&CdxQuery { limit: Some(2), ..CdxQuery::new("davemolk.com") }).collect::<Result<Vec<_>>>().unwrap();
+        let years = got.iter().map(|i| i.timestamp()[..4].to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["2014", "2015", "2016", "2017"], years);
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(3, requests.len());
+        assert!(requests[0].ends_with("&limit=2&showResumeKey=true"));
+        assert!(requests[2].ends_with("&showResumeKey=true&resumeKey=three"));
+    }
+    #[test]
+    fn iter_stops_at_max_results() {
+        let server = TestServer::start(|_| {
+            Response::ok(format!("[{},{},{},[],[\"more\"]]", HEADER, row("20140101000000"), row("20150101000000")).as_bytes())
+        });
+        let client = CdxClient::with_base(&format!("{}/cdx?url=", server.base)).max_results(Some(3));
+        let got = client.iter(&CdxQuery::new("davemolk.com")).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(3, got.len());
+        // no request for a page it won't use
+        assert_eq!(2, server.requests.lock().unwrap().len());
+        assert!(server.requests.lock().unwrap()[0].contains(&format!("&limit={}", DEFAULT_PAGE_SIZE)));
+    }
+    #[test]
+    fn iter_pages() {
+        let server = TestServer::start(|path| {
+            if path.ends_with("showNumPages=true") {
+                return Response::ok(b"3\n");
+            }
+            let page = path.rsplit("page=").next().unwrap();
+            let body = match page {
+                "0" => format!("[{},{}]", HEADER, row("20140101000000")),
+                "1" => format!("[{}]", HEADER),
+                _ => format!("[{},{}]", HEADER, row("20160101000000")),
+            };
+            Response::ok(body.as_bytes())
+        });
+        let client = CdxClient::with_base(&format!("{}/cdx?url=", server.base)).paging(Paging::Pages);
+        let got = client.iter(&CdxQuery::new("davemolk.com")).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(2, got.len());
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(4, requests.len());
+        assert!(!requests[0].contains("limit="));
+        assert!(requests[3].ends_with("&page=2"));
+    }
+    #[test]
+    fn iter_offline_from_fixture() {
+        let dir = std::env::temp_dir().join(format!("wayback_cdx_cache_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = Cache::new(&dir).offline(true);
+        let client = CdxClient::new().cache(Some(cache.clone()));
+        let query = CdxQuery::new("davemolk.com");
+        // nothing recorded yet, and offline means not asking
+        assert!(client.iter(&query).next().unwrap().unwrap_err().to_string().contains("isn't in the cache"));
+        let url = format!("{}&limit={}&showResumeKey=true", client.get_query_url(&query), DEFAULT_PAGE_SIZE);
+        cache.put_cdx(&url, &fs::read_to_string("tests/cdx.json").unwrap()).unwrap();
+        assert_eq!(20, client.iter(&query).collect::<Result<Vec<_>>>().unwrap().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn iter_saves_to_cache() {
+        let dir = std::env::temp_dir().join(format!("wayback_cdx_cache_save_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let server = TestServer::start(|_| Response::ok(format!("[{},{}]", HEADER, row("20140101000000")).as_bytes()));
+        let client = CdxClient::with_base(&format!("{}/cdx?url=", server.base)).cache(Some(Cache::new(&dir)));
+        let query = CdxQuery::new("davemolk.com");
+        assert_eq!(1, client.iter(&query).count());
+        assert_eq!(1, client.iter(&query).count());
+        assert_eq!(1, server.requests.lock().unwrap().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn iter_replays_fixtures() {
+        let replay = Arc::new(Replay::new()
+            .fixture("resumeKey=two", &format!("[{},{}]", HEADER, row("20160101000000")))
+            .fixture("", &format!("[{},{},[],[\"two\"]]", HEADER, row("20150101000000"))));
+        let client = CdxClient::new().transport(replay.clone());
+        let rows = client.get_cdx(&CdxQuery::new("davemolk.com")).unwrap();
+        assert_eq!(vec!["20150101000000", "20160101000000"], rows.iter().map(CdxInfo::timestamp).collect::<Vec<_>>());
+        assert_eq!(2, replay.requests().len());
+        assert!(replay.requests()[0].starts_with(CDX_BASE));
+    }
+    #[test]
+    fn iter_stops_after_error() {
+        let server = TestServer::start(|_| Response::status(503));
+        let client = CdxClient::with_base(&format!("{}/cdx?url=", server.base));
+        let mut iter = client.iter(&CdxQuery::new("davemolk.com"));
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use std::thread;
+use std::time::{Duration, Instant};
+use wayback_api::limiter::RateLimiter;
+use wayback_api::transport::USER_AGENT;
+
+use crate::cache::Cache;
+use crate::cdx::{CdxInfo, WAYBACK_WEB_URL_BASE};
+use crate::download::{digest, is_digest};
+use crate::warc::archived_headers;
+
+const DEFAULT_RATE: f64 = 2.0;
+const DEFAULT_RETRIES: u32 = 5;
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+// don't let a server park us forever
+const MAX_WAIT: Duration = Duration::from_secs(300);
+
+/// http headers, name and value.
+pub type Headers = Vec<(String, String)>;
+
+pub struct WaybackClient {
+    client: reqwest::blocking::Client,
+    base: String,
+    limiter: RateLimiter,
+    retries: u32,
+    backoff: Duration,
+    cache: Option<Cache>,
+}
+
+impl WaybackClient {
+    pub fn new() -> Self {
+        WaybackClient::with_base(WAYBACK_WEB_URL_BASE)
+    }
+    /// points the client somewhere other than web.archive.org, mostly for tests.
+    pub fn with_base(base: &str) -> Self {
+        WaybackClient{
+            client: reqwest::blocking::Client::new(),
+            base: base.to_owned(),
+            limiter: RateLimiter::new(DEFAULT_RATE),
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            cache: None,
+        }
+    }
+    /// requests per second across every thread using this client, 0 for no limit.
+    pub fn rate(mut self, per_second: f64) -> Self {
+        self.limiter = RateLimiter::new(per_second);
+        self
+    }
+    /// how many times to retry throttling, server errors, and dropped connections,
+    /// waiting backoff, then twice that, and so on in between.
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+    /// keep snapshots in (and take them from) an on-disk cache, by digest.
+    pub fn cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+    /// gets the body of a snapshot. raw skips the wayback machine's rewriting (id_).
+    pub fn fetch(&self, info: &CdxInfo, raw: bool) -> Result<Vec<u8>> {
+        Ok(self.fetch_with_headers(info, raw)?.0)
+    }
+    /// the body of a snapshot and the http headers it was archived with, if the
+    /// wayback machine said (the cache doesn't keep them).
+    pub fn fetch_with_headers(&self, info: &CdxInfo, raw: bool) -> Result<(Vec<u8>, Headers)> {
+        let url = info.web_url_from(&self.base, raw);
+        if let Some(cache) = &self.cache {
+            if let Some(body) = cache.snapshot(info, raw) {
+                return Ok((body, Vec::new()));
+            }
+            if cache.is_offline() {
+                return Err(cache.missing(&url));
+            }
+        }
+        let (body, headers) = self.fetch_url(&url)?;
+        if let Some(cache) = &self.cache {
+            // a raw body that doesn't match its digest shouldn't be filed under it
+            if !raw || !is_digest(&info.digest) || digest(&body) == info.digest {
+                cache.put_snapshot(info, raw, &body)?;
+            }
+        }
+        Ok((body, headers))
+    }
+    fn fetch_url(&self, url: &str) -> Result<(Vec<u8>, Headers)> {
+        let mut attempt = 0;
+        loop {
+            self.limiter.wait();
+            let err = match self.client.get(url).header(reqwest::header::USER_AGENT, USER_AGENT).send() {
+                Ok(resp) if resp.status().is_success() => {
+                    let headers = archived_headers(resp.headers().iter().filter_map(|(k, v)| Some((k.as_str(), v.to_str().ok()?))));
+                    match resp.bytes() {
+                        Ok(body) => return Ok((body.to_vec(), headers)),
+                        // the connection going away mid-body is as worth retrying as before it
+                        Err(e) => anyhow!(e).context("failed reading the body"),
+                    }
+                },
+                Ok(resp) if is_retryable(resp.status()) => {
+                    if let Some(wait) = retry_after(&resp) {
+                        self.limiter.pause_until(Instant::now() + wait);
+                    }
+                    anyhow!("unexpected response status {}", resp.status())
+                },
+                Ok(resp) => return Err(anyhow!("unexpected response status {}", resp.status())),
+                // connection refused, reset, timed out and so on
+                Err(e) if !e.is_builder() && !e.is_redirect() && !e.is_decode() => anyhow!(e),
+                Err(e) => return Err(e.into()),
+            };
+            if attempt >= self.retries {
+                return Err(err.context(format!("gave up after {} attempts", attempt + 1)));
+            }
+            thread::sleep((self.backoff * 2u32.saturating_pow(attempt)).min(MAX_WAIT));
+            attempt += 1;
+        }
+    }
+}
+
+impl Default for WaybackClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// Retry-After is either a number of seconds or an http date
+fn retry_after(resp: &reqwest::blocking::Response) -> Option<Duration> {
+    let value = resp.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    let wait = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let at = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+            (at - Utc::now()).to_std().unwrap_or(Duration::ZERO)
+        },
+    };
+    Some(wait.min(MAX_WAIT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayback_api::test_server::{Response, TestServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn info(original: &str) -> CdxInfo {
+        CdxInfo::parse_row(original, "20231031041854", "200", "text/html", "3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ", "0").unwrap()
+    }
+
+    #[test]
+    fn test_fetch_retries_then_succeeds() {
+        let count = AtomicUsize::new(0);
+        let server = TestServer::start(move |_| match count.fetch_add(1, Ordering::SeqCst) {
+            0 => Response::status(503),
+            1 => Response::status(429).header("Retry-After", "1"),
+            _ => Response::ok(b"finally"),
+        });
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(3, Duration::from_millis(10));
+        let start = Instant::now();
+        let body = client.fetch(&info("https://davemolk.com/"), true).unwrap();
+        assert_eq!(b"finally".to_vec(), body);
+        assert_eq!(3, server.requests.lock().unwrap().len());
+        // the Retry-After pause is longer than the backoff
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+    #[test]
+    fn test_fetch_gives_up() {
+        let server = TestServer::start(|_| Response::status(500));
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(2, Duration::from_millis(1));
+        let err = client.fetch(&info("https://davemolk.com/"), true).unwrap_err();
+        assert!(err.to_string().contains("gave up after 3 attempts"));
+        assert_eq!(3, server.requests.lock().unwrap().len());
+    }
+    #[test]
+    fn test_fetch_does_not_retry_404() {
+        let server = TestServer::start(|_| Response::status(404));
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(2, Duration::from_millis(1));
+        assert!(client.fetch(&info("https://davemolk.com/"), true).is_err());
+        assert_eq!(1, server.requests.lock().unwrap().len());
+    }
+    #[test]
+    fn test_fetch_cache() {
+        let dir = std::env::temp_dir().join(format!("wayback_client_cache_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let server = TestServer::start(|_| Response::ok(b""));
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).cache(Some(Cache::new(&dir)));
+        assert_eq!(b"".to_vec(), client.fetch(&info("https://davemolk.com/"), true).unwrap());
+        // same digest, so it's the same content whatever the url
+        assert_eq!(b"".to_vec(), client.fetch(&info("https://davemolk.com/copy"), true).unwrap());
+        assert_eq!(1, server.requests.lock().unwrap().len());
+        // rewritten (if_) content is kept apart from raw
+        let offline = WaybackClient::with_base(&format!("{}/", server.base)).cache(Some(Cache::new(&dir).offline(true)));
+        assert!(offline.fetch(&info("https://davemolk.com/"), false).is_err());
+        assert!(offline.fetch(&info("https://davemolk.com/"), true).is_ok());
+        assert_eq!(1, server.requests.lock().unwrap().len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_fetch_retries_cut_off_body() {
+        let count = AtomicUsize::new(0);
+        let server = TestServer::start(move |_| match count.fetch_add(1, Ordering::SeqCst) {
+            0 => Response::ok(b"the whole thing").cut_after(3),
+            _ => Response::ok(b"the whole thing"),
+        });
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(1, Duration::from_millis(1));
+        assert_eq!(b"the whole thing".to_vec(), client.fetch(&info("https://davemolk.com/"), true).unwrap());
+        assert_eq!(2, server.requests.lock().unwrap().len());
+        // and gives up like anything else
+        let server = TestServer::start(|_| Response::ok(b"never all of it").cut_after(5));
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(1, Duration::from_millis(1));
+        let err = client.fetch(&info("https://davemolk.com/"), true).unwrap_err();
+        assert!(err.to_string().contains("gave up after 2 attempts"));
+    }
+    #[test]
+    fn test_fetch_retries_connection_errors() {
+        // nothing is listening here once the listener is dropped
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let client = WaybackClient::with_base(&format!("http://{}/", addr)).rate(0.0).retries(1, Duration::from_millis(1));
+        let err = client.fetch(&info("https://davemolk.com/"), true).unwrap_err();
+        assert!(err.to_string().contains("gave up after 2 attempts"));
+    }
+}
@@ -0,0 +1,261 @@
+// what changed between captures of a page
+use anyhow::Result;
+use similar::{ChangeTag, TextDiff};
+use std::fmt;
+
+use crate::cdx::{parse_timestamp, CdxClient, CdxInfo, CdxQuery, DATE_FORMAT};
+use crate::client::WaybackClient;
+
+/// a unified diff of the text of url at two timestamps, fetched raw (id_).
+/// wayback snaps partial or missing timestamps to the closest capture.
+pub fn diff_captures(client: &WaybackClient, url: &str, ts1: &str, ts2: &str) -> Result<String> {
+    let old = fetch_text(client, &capture(url, ts1)?)?;
+    let new = fetch_text(client, &capture(url, ts2)?)?;
+    Ok(TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("{} {}", url, ts1), &format!("{} {}", url, ts2))
+        .to_string())
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub from: String,
+    pub to: String,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl Change {
+    /// the digest changing isn't enough, plenty of captures only differ in
+    /// whitespace or the markup wayback adds.
+    pub fn is_material(&self) -> bool {
+        self.added + self.removed > 0
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_material() {
+            write!(f, "{} -> {}: +{} -{} lines", self.from, self.to, self.added, self.removed)
+        } else {
+            write!(f, "{} -> {}: no material change", self.from, self.to)
+        }
+    }
+}
+
+/// walks the unique-digest 200 captures of query in order, comparing each with
+/// the one before it. each capture is fetched once.
+pub fn changes(cdx: &CdxClient, client: &WaybackClient, query: &CdxQuery) -> Result<Vec<Change>> {
+    let mut query = query.clone();
+    query.unique = true;
+    query.filters.push("statuscode:200".to_owned());
+    let mut out = Vec::new();
+    let mut previous: Option<(CdxInfo, String)> = None;
+    for info in cdx.iter(&query) {
+        let info = info?;
+        let text = fetch_text(client, &info)?;
+        if let Some((before, before_text)) = &previous {
+            let (added, removed) = count_changes(before_text, &text);
+            out.push(Change { from: before.timestamp(), to: info.timestamp(), added, removed });
+        }
+        previous = Some((info, text));
+    }
+    Ok(out)
+}
+
+fn capture(url: &str, timestamp: &str) -> Result<CdxInfo> {
+    let at = parse_timestamp(timestamp)?.format(DATE_FORMAT).to_string();
+    CdxInfo::parse_row(url, &at, "-", "-", "-", "-")
+}
+
+fn fetch_text(client: &WaybackClient, info: &CdxInfo) -> Result<String> {
+    let body = client.fetch(info, true)?;
+    Ok(normalize(&strip_wayback(&String::from_utf8_lossy(&body))))
+}
+
+fn count_changes(old: &str, new: &str) -> (usize, usize) {
+    let diff = TextDiff::from_lines(old, new);
+    let mut added = 0;
+    let mut removed = 0;
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => added += 1,
+            ChangeTag::Delete => removed += 1,
+            ChangeTag::Equal => {},
+        }
+    }
+    (added, removed)
+}
+
+// blank lines and indentation come and go without anything really changing
+fn normalize(text: &str) -> String {
+    let mut out = String::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// takes out what the wayback machine adds to the pages it serves: the toolbar,
+/// its scripts, the archived-on comment at the end, and /web/<timestamp>/
+/// in front of links. raw captures shouldn't have any, but not all of them are.
+pub fn strip_wayback(html: &str) -> String {
+    let mut text = remove_between(html, "<!-- BEGIN WAYBACK TOOLBAR INSERT -->", "<!-- END WAYBACK TOOLBAR INSERT -->");
+    let end = "<!-- End Wayback Rewrite JS Include -->";
+    if let Some(stop) = text.find(end) {
+        // the include has no start marker, it's everything from wayback's first script
+        let start = ["<script src=\"//archive.org/includes/", "<script type=\"text/javascript\" src=\"/_static/", "<script src=\"/_static/"]
+            .iter()
+            .filter_map(|marker| text[..stop].find(marker))
+            .min()
+            .unwrap_or(stop);
+        text.replace_range(start..stop + end.len(), "");
+    }
+    if let Some(start) = text.find("<!--\n     FILE ARCHIVED ON") {
+        if let Some(stop) = text[start..].find("-->") {
+            text.replace_range(start..start + stop + 3, "");
+        }
+    }
+    unwrap_links(&text)
+}
+
+fn remove_between(text: &str, start: &str, end: &str) -> String {
+    let mut out = text.to_owned();
+    while let Some(from) = out.find(start) {
+        let Some(to) = out[from..].find(end) else {
+            break;
+        };
+        out.replace_range(from..from + to + end.len(), "");
+    }
+    out
+}
+
+// http://web.archive.org/web/20150925144711im_/http://davemolk.com/ back to
+// http://davemolk.com/
+fn unwrap_links(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    while let Some(i) = text[pos..].find("/web/") {
+        let start = pos + i;
+        let rest = &text[start + 5..];
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let flags = rest[digits..].chars().take_while(|c| c.is_ascii_lowercase() || *c == '_').count();
+        let after = &rest[digits + flags..];
+        let prefix = ["https://web.archive.org", "http://web.archive.org", "//web.archive.org"]
+            .iter()
+            .find(|p| text[..start].ends_with(*p));
+        let wraps = digits > 0 && digits <= 14 && after.starts_with('/')
+            && (prefix.is_some() || after.starts_with("/http://") || after.starts_with("/https://"));
+        if !wraps {
+            out.push_str(&text[pos..start + 5]);
+            pos = start + 5;
+            continue;
+        }
+        out.push_str(&text[pos..start - prefix.map(|p| p.len()).unwrap_or(0)]);
+        pos = start + 5 + digits + flags + 1;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayback_api::test_server::{Response, TestServer};
+    use std::time::Duration;
+
+    const PAGE: &str = r#"<html><head><script src="//archive.org/includes/analytics.js?v=cf34f82" type="text/javascript"></script>
+<script type="text/javascript" src="/_static/js/bundle-playback.js"></script>
+<!-- End Wayback Rewrite JS Include -->
+<title>pricing</title></head><body>
+<!-- BEGIN WAYBACK TOOLBAR INSERT -->
+<div id="wm-ipp">toolbar</div>
+<!-- END WAYBACK TOOLBAR INSERT -->
+<a href="https://web.archive.org/web/20150925144711/http://davemolk.com/about">about</a>
+<img src="/web/20150925144711im_/http://davemolk.com/a.png"><a href="/web/2015/about">not wayback</a>
+</body></html>
+<!--
+     FILE ARCHIVED ON 14:47:11 Sep 25, 2015 AND RETRIEVED FROM THE
+     INTERNET ARCHIVE ON 20:00:00 Oct 18, 2026.
+-->"#;
+
+    #[test]
+    fn test_strip_wayback() {
+        let want = r#"<html><head>
+<title>pricing</title></head><body>
+
+<a href="http://davemolk.com/about">about</a>
+<img src="http://davemolk.com/a.png"><a href="/web/2015/about">not wayback</a>
+</body></html>
+"#;
+        assert_eq!(want, strip_wayback(PAGE));
+    }
+    #[test]
+    fn test_count_changes() {
+        assert_eq!((0, 0), count_changes(&normalize("a\n\n  b\n"), &normalize("a\nb")));
+        assert_eq!((2, 1), count_changes("a\nb\n", "a\nc\nd\n"));
+    }
+    #[test]
+    fn test_diff_captures() {
+        let server = TestServer::start(|path| match path {
+            "/20150101000000id_/http://davemolk.com/pricing" => Response::ok(b"<h1>pricing</h1>\n<p>$10</p>\n"),
+            _ => Response::ok(format!("<h1>pricing</h1>\n{}\n  <p>$12</p>", PAGE).as_bytes()),
+        });
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(0, Duration::ZERO);
+        let diff = diff_captures(&client, "http://davemolk.com/pricing", "2015", "20160101").unwrap();
+        assert!(diff.starts_with("--- http://davemolk.com/pricing 2015\n+++ http://davemolk.com/pricing 20160101\n"));
+        assert!(diff.contains("\n-<p>$10</p>\n"));
+        assert!(diff.contains("\n+<p>$12</p>\n"));
+        assert!(!diff.contains("toolbar"));
+        assert_eq!(1, server.hits("/20160101000000id_/http://davemolk.com/pricing"));
+    }
+    #[test]
+    fn test_diff_captures_offline() {
+        let server = TestServer::start(|path| match path {
+            "/20150101000000id_/http://davemolk.com/pricing" => Response::ok(b"<p>$10</p>\n"),
+            _ => Response::ok(b"<p>$12</p>\n"),
+        });
+        let dir = std::env::temp_dir().join(format!("wayback_diff_cache_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = crate::cache::Cache::new(&dir);
+        let online = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(0, Duration::ZERO).cache(Some(cache.clone()));
+        let diff = diff_captures(&online, "http://davemolk.com/pricing", "2015", "2016").unwrap();
+        // cdx has no digest for these, they're cached by capture
+        let offline = WaybackClient::with_base("http://127.0.0.1:1/").cache(Some(cache.offline(true)));
+        assert_eq!(diff, diff_captures(&offline, "http://davemolk.com/pricing", "2015", "2016").unwrap());
+        assert!(diff_captures(&offline, "http://davemolk.com/pricing", "2015", "2017").is_err());
+        assert_eq!(2, server.requests.lock().unwrap().len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_changes() {
+        let server = TestServer::start(|path| {
+            if path.starts_with("/cdx") {
+                let row = |ts: &str| format!(r#"["http://davemolk.com/pricing","{}","200","text/html","X{}","10"]"#, ts, ts);
+                let body = format!(
+                    r#"[["original","timestamp","statuscode","mimetype","digest","length"],{},{},{}]"#,
+                    row("20150101000000"), row("20160101000000"), row("20170101000000"),
+                );
+                return Response::ok(body.as_bytes());
+            }
+            match &path[1..5] {
+                "2015" => Response::ok(b"<p>$10</p>\n"),
+                "2016" => Response::ok(b"  <p>$10</p>\n\n"),
+                _ => Response::ok(b"<p>$12</p>\n<p>new plan</p>\n"),
+            }
+        });
+        let cdx = CdxClient::with_base(&format!("{}/cdx?url=", server.base));
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(0, Duration::ZERO);
+        let got = changes(&cdx, &client, &CdxQuery::new("http://davemolk.com/pricing")).unwrap();
+        assert_eq!(2, got.len());
+        assert!(!got[0].is_material());
+        assert_eq!(Change { from: "20160101000000".to_owned(), to: "20170101000000".to_owned(), added: 2, removed: 1 }, got[1]);
+        assert_eq!("20160101000000 -> 20170101000000: +2 -1 lines", got[1].to_string());
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].contains("&collapse=digest&filter=statuscode:200"));
+        // one cdx request and one fetch per capture
+        assert_eq!(4, requests.len());
+    }
+}
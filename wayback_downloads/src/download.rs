@@ -0,0 +1,368 @@
+use anyhow::{anyhow, Result};
+use data_encoding::BASE32;
+use serde_derive::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::cdx::CdxInfo;
+use crate::client::WaybackClient;
+use crate::manifest::{Manifest, Status};
+use crate::warc::WarcWriter;
+
+/// what a download job writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    /// one file per capture, <dir>/<host>/<timestamp>/<path>.
+    #[default]
+    Files,
+    /// WARC 1.1 files in <dir>, keeping timestamps, urls and headers.
+    Warc,
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub downloaded: usize,
+    pub bytes: u64,
+    pub already_present: usize,
+    // redirects, errors, and revisits don't have anything worth saving
+    pub not_ok: usize,
+    // raw downloads that don't match their cdx digest
+    pub mismatched: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "downloaded:      {} ({} bytes)", self.downloaded, self.bytes)?;
+        writeln!(f, "already present: {}", self.already_present)?;
+        writeln!(f, "skipped non-2xx: {}", self.not_ok)?;
+        if self.mismatched > 0 {
+            writeln!(f, "digest mismatch: {} (see wayback_downloads verify)", self.mismatched)?;
+        }
+        write!(f, "failed:          {}", self.failed.len())?;
+        for (url, err) in &self.failed {
+            write!(f, "\n  {}: {}", url, err)?;
+        }
+        Ok(())
+    }
+}
+
+/// where a snapshot goes on disk, <dir>/<host>/<timestamp>/<path>.
+/// directories get an index.html and query strings stay on the file name.
+pub fn local_path(dir: &Path, info: &CdxInfo) -> Result<PathBuf> {
+    let url = reqwest::Url::parse(&info.original)?;
+    let host = url.host_str().ok_or_else(|| anyhow!("no host in {}", info.original))?;
+    let mut path = dir.join(host).join(info.timestamp());
+    let segments = url.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>()).unwrap_or_default();
+    let mut file = match segments.split_last() {
+        Some((last, parents)) if !url.path().ends_with('/') => {
+            path.extend(parents);
+            last.to_string()
+        },
+        _ => {
+            path.extend(segments);
+            "index.html".to_string()
+        },
+    };
+    if let Some(query) = url.query() {
+        file.push('?');
+        file.push_str(&query.replace('/', "%2F"));
+    }
+    Ok(path.join(file))
+}
+
+/// sha1 in base32, the same way cdx reports digests.
+pub fn digest(data: &[u8]) -> String {
+    BASE32.encode(&Sha1::digest(data))
+}
+
+/// whether cdx gave a real digest, rather than "-" or nothing.
+pub fn is_digest(s: &str) -> bool {
+    s.len() == 32 && s.chars().all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c))
+}
+
+// how many finished files between manifest saves, saving after every single
+// one gets slow with thousands of rows
+const SAVE_EVERY: usize = 20;
+
+/// downloads everything the manifest still has to do into its directory with a
+/// pool of workers, skipping files that are already there with a matching digest.
+/// failures are recorded instead of stopping the run, progress goes to stderr,
+/// and the manifest is saved as it goes so the job can be resumed.
+pub fn download_all(client: &WaybackClient, manifest: &mut Manifest, concurrency: usize) -> Result<Summary> {
+    // a warc record stands in for the original response, so it gets the original
+    // bytes whatever --raw says
+    let raw = manifest.query.raw || manifest.query.output == Output::Warc;
+    let warc = match manifest.query.output {
+        Output::Files => None,
+        Output::Warc => Some(Mutex::new(WarcWriter::new(manifest.dir(), manifest.query.gzip))),
+    };
+    let todo = manifest.todo();
+    let total = todo.len();
+    let dir = manifest.dir().to_owned();
+    let jobs = todo.iter()
+        .map(|&i| (i, manifest.entries[i].info.clone(), manifest.entries[i].file_digest.clone()))
+        .collect::<Vec<_>>();
+    let state = Mutex::new((manifest, Summary::default(), 0));
+    let next = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for _ in 0..concurrency.max(1) {
+            s.spawn(|| while let Some((i, info, file_digest)) = jobs.get(next.fetch_add(1, Ordering::SeqCst)) {
+                let outcome = if info.status_code.is_some_and(|s| (200..300).contains(&s)) {
+                    Some(match &warc {
+                        Some(writer) => download_warc(client, info, writer),
+                        None => download_one(client, info, &dir, raw, file_digest.as_deref()),
+                    })
+                } else {
+                    None
+                };
+                let mut state = state.lock().unwrap();
+                let (manifest, summary, done) = &mut *state;
+                *done += 1;
+                let progress = format!("[{}/{}]", done, total);
+                let entry = &mut manifest.entries[*i];
+                match outcome {
+                    None => {
+                        summary.not_ok += 1;
+                        entry.status = Status::Skipped;
+                        eprintln!("{} skipped {} ({})", progress, info.original, info.status_code.map(|s| s.to_string()).unwrap_or("-".to_string()));
+                    },
+                    Some(Ok(written)) => {
+                        // anything but raw is rewritten by wayback, so can't match
+                        let mismatch = raw && is_digest(&info.digest) && written.digest != info.digest;
+                        if mismatch {
+                            summary.mismatched += 1;
+                        }
+                        if written.fresh {
+                            summary.downloaded += 1;
+                            summary.bytes += written.bytes;
+                            let note = if mismatch { " (digest mismatch)" } else { "" };
+                            eprintln!("{} downloaded {}{}", progress, info.original, note);
+                        } else {
+                            summary.already_present += 1;
+                            eprintln!("{} already have {}", progress, info.original);
+                        }
+                        entry.status = Status::Done;
+                        entry.bytes = Some(written.bytes);
+                        entry.file_digest = Some(written.digest);
+                        entry.error = None;
+                        entry.rewritten = false;
+                    },
+                    Some(Err(e)) => {
+                        eprintln!("{} failed {}: {:#}", progress, info.original, e);
+                        summary.failed.push((info.web_url(raw), format!("{:#}", e)));
+                        entry.status = Status::Failed;
+                        entry.error = Some(format!("{:#}", e));
+                    },
+                }
+                if *done % SAVE_EVERY == 0 {
+                    if let Err(e) = manifest.save() {
+                        eprintln!("failed to save manifest: {}", e);
+                    }
+                }
+            });
+        }
+    });
+    let (manifest, summary, _) = state.into_inner().unwrap();
+    manifest.save()?;
+    Ok(summary)
+}
+
+struct Written {
+    bytes: u64,
+    digest: String,
+    // false if the file was already there
+    fresh: bool,
+}
+
+// file_digest is what the manifest has for the file from before, if anything.
+// the cdx digest only says anything about raw files
+fn download_one(client: &WaybackClient, info: &CdxInfo, dir: &Path, raw: bool, file_digest: Option<&str>) -> Result<Written> {
+    let path = local_path(dir, info)?;
+    if let Ok(existing) = fs::read(&path) {
+        let existing_digest = digest(&existing);
+        if Some(existing_digest.as_str()) == file_digest || (raw && existing_digest == info.digest) {
+            return Ok(Written { bytes: existing.len() as u64, digest: existing_digest, fresh: false });
+        }
+    }
+    let body = client.fetch(info, raw)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, &body)?;
+    Ok(Written { bytes: body.len() as u64, digest: digest(&body), fresh: true })
+}
+
+fn download_warc(client: &WaybackClient, info: &CdxInfo, writer: &Mutex<WarcWriter>) -> Result<Written> {
+    let (body, headers) = client.fetch_with_headers(info, true)?;
+    writer.lock().unwrap().write_capture(info, &headers, &body)?;
+    Ok(Written { bytes: body.len() as u64, digest: digest(&body), fresh: true })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdx::CdxQuery;
+    use crate::manifest::Query;
+    use wayback_api::test_server::{Response, TestServer};
+    use std::time::{Duration, Instant};
+
+    fn info(original: &str, status: &str, body: &[u8]) -> CdxInfo {
+        CdxInfo::parse_row(original, "20231031041854", status, "text/html", &digest(body), &body.len().to_string()).unwrap()
+    }
+    fn query() -> Query {
+        Query { cdx: CdxQuery { unique: true, ..CdxQuery::new("davemolk.com") }, raw: true, max_results: None, ..Default::default() }
+    }
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wayback_downloads_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_digest() {
+        // sha1 of the empty string, base32
+        assert_eq!("3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ", digest(b""));
+    }
+    #[test]
+    fn test_local_path() {
+        let dir = Path::new("out");
+        let cases = [
+            ("https://davemolk.com/", "out/davemolk.com/20231031041854/index.html"),
+            ("https://davemolk.com", "out/davemolk.com/20231031041854/index.html"),
+            ("https://davemolk.com/posts/", "out/davemolk.com/20231031041854/posts/index.html"),
+            ("https://davemolk.com/css/main.css", "out/davemolk.com/20231031041854/css/main.css"),
+            ("https://davemolk.com/search?q=a/b", "out/davemolk.com/20231031041854/search?q=a%2Fb"),
+            ("https://davemolk.com/../../etc/passwd", "out/davemolk.com/20231031041854/etc/passwd"),
+        ];
+        for (original, want) in cases {
+            let got = local_path(dir, &info(original, "200", b"")).unwrap();
+            assert_eq!(PathBuf::from(want), got, "{}", original);
+        }
+    }
+    #[test]
+    fn test_download_all() {
+        let server = TestServer::start(|path| match path {
+            "/20231031041854id_/https://davemolk.com/" => Response::ok(b"<html>home</html>"),
+            "/20231031041854id_/https://davemolk.com/about" => Response::ok(b"about"),
+            _ => Response::status(404),
+        });
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(0, Duration::ZERO);
+        let dir = temp_dir("download_all");
+        let results = vec![
+            info("https://davemolk.com/", "200", b"<html>home</html>"),
+            info("https://davemolk.com/about", "200", b"about"),
+            info("https://davemolk.com/moved", "301", b""),
+            info("https://davemolk.com/gone", "200", b""),
+        ];
+        let mut manifest = Manifest::create(&dir, query(), results).unwrap();
+        let summary = download_all(&client, &mut manifest, 3).unwrap();
+        assert_eq!(2, summary.downloaded);
+        assert_eq!(22, summary.bytes);
+        assert_eq!(1, summary.not_ok);
+        assert_eq!(1, summary.failed.len());
+        let home = dir.join("davemolk.com/20231031041854/index.html");
+        assert_eq!("<html>home</html>", fs::read_to_string(&home).unwrap());
+
+        assert_eq!(0, summary.mismatched);
+        let statuses = manifest.entries.iter().map(|e| e.status).collect::<Vec<_>>();
+        assert_eq!(vec![Status::Done, Status::Done, Status::Skipped, Status::Failed], statuses);
+        assert_eq!(Some(digest(b"about")), manifest.entries[1].file_digest);
+
+        // a resumed job only retries what failed
+        let mut resumed = Manifest::load(&dir).unwrap();
+        assert_eq!(vec![3], resumed.todo());
+        let summary = download_all(&client, &mut resumed, 1).unwrap();
+        assert_eq!(0, summary.downloaded);
+        assert_eq!(1, summary.failed.len());
+        assert_eq!(1, server.hits("/20231031041854id_/https://davemolk.com/"));
+
+        // a fresh job skips matching digests and fetches changed files again
+        fs::write(&home, "tampered").unwrap();
+        fs::remove_file(dir.join(crate::manifest::MANIFEST_FILE)).unwrap();
+        let mut manifest = Manifest::create(&dir, query(), manifest.entries.iter().take(2).map(|e| e.info.clone()).collect()).unwrap();
+        let summary = download_all(&client, &mut manifest, 1).unwrap();
+        assert_eq!(1, summary.downloaded);
+        assert_eq!(1, summary.already_present);
+        assert_eq!(2, server.hits("/20231031041854id_/https://davemolk.com/"));
+        assert_eq!(1, server.hits("/20231031041854id_/https://davemolk.com/about"));
+        assert_eq!("<html>home</html>", fs::read_to_string(&home).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_download_all_resume_not_raw() {
+        // rewritten by wayback, so nothing like the cdx digest
+        let server = TestServer::start(|path| match path {
+            "/20231031041854if_/https://davemolk.com/" => Response::ok(b"<html>home, rewritten</html>"),
+            _ => Response::status(404),
+        });
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(0, Duration::ZERO);
+        let dir = temp_dir("resume_not_raw");
+        let results = vec![info("https://davemolk.com/", "200", b"<html>home</html>")];
+        let mut manifest = Manifest::create(&dir, Query { raw: false, ..query() }, results).unwrap();
+        download_all(&client, &mut manifest, 1).unwrap();
+        assert_eq!(Some(digest(b"<html>home, rewritten</html>")), manifest.entries[0].file_digest);
+        // as if it needs doing again, the file on disk matches what the manifest has
+        manifest.entries[0].status = Status::Failed;
+        let summary = download_all(&client, &mut manifest, 1).unwrap();
+        assert_eq!(1, summary.already_present);
+        assert_eq!(1, server.hits("/20231031041854if_/https://davemolk.com/"));
+        // unless it changed
+        fs::write(dir.join("davemolk.com/20231031041854/index.html"), "tampered").unwrap();
+        manifest.entries[0].status = Status::Failed;
+        let summary = download_all(&client, &mut manifest, 1).unwrap();
+        assert_eq!(1, summary.downloaded);
+        assert_eq!(2, server.hits("/20231031041854if_/https://davemolk.com/"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_download_all_warc() {
+        let server = TestServer::start(|path| match path {
+            "/20231031041854id_/https://davemolk.com/" => Response::ok(b"<html>home</html>").header("X-Archive-Orig-Content-Type", "text/html"),
+            _ => Response::ok(b"<html>rewritten by wayback</html>"),
+        });
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(0.0).retries(0, Duration::ZERO);
+        let dir = temp_dir("warc");
+        let results = vec![info("https://davemolk.com/", "200", b"<html>home</html>")];
+        // not --raw, but warc gets the original anyway
+        let query = Query { output: Output::Warc, raw: false, ..query() };
+        let mut manifest = Manifest::create(&dir, query, results).unwrap();
+        let summary = download_all(&client, &mut manifest, 1).unwrap();
+        assert_eq!(1, summary.downloaded);
+        assert!(!dir.join("davemolk.com").exists());
+        let warc = fs::read_to_string(dir.join("wayback-00000.warc")).unwrap();
+        assert!(warc.contains("WARC-Target-URI: https://davemolk.com/\r\n"));
+        assert!(warc.contains("HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n"));
+        assert!(warc.contains("<html>home</html>"));
+        assert!(!warc.contains("rewritten"));
+        assert_eq!(0, summary.mismatched);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_download_all_concurrent_and_rate_limited() {
+        let server = TestServer::start(|path| Response::ok(path.as_bytes()));
+        let client = WaybackClient::with_base(&format!("{}/", server.base)).rate(40.0);
+        let dir = temp_dir("concurrent");
+        let results = (0..10)
+            .map(|i| {
+                let original = format!("https://davemolk.com/{}", i);
+                let body = format!("/20231031041854id_/{}", original);
+                info(&original, "200", body.as_bytes())
+            })
+            .collect::<Vec<_>>();
+        let start = Instant::now();
+        let mut manifest = Manifest::create(&dir, query(), results).unwrap();
+        let summary = download_all(&client, &mut manifest, 4).unwrap();
+        assert_eq!(10, summary.downloaded);
+        assert!(summary.failed.is_empty());
+        // ten requests at 40/s, no matter how many workers
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
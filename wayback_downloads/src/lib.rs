@@ -1,17 +1,41 @@
 use anyhow::{anyhow, Result};
-use clap::Parser;
-use chrono::NaiveDateTime;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
 
-mod cdx;
+pub mod cache;
+pub mod cdx;
+pub mod client;
+pub mod diff;
+pub mod download;
+mod listing;
+pub mod manifest;
+pub mod rewrite;
+pub mod stats;
+pub mod verify;
+pub mod warc;
+
+use cache::Cache;
+use cdx::{CdxClient, CdxQuery, MatchType, Paging};
+use client::WaybackClient;
+use download::Output;
+use listing::Format;
+use manifest::{Manifest, Query};
 
 #[derive(Debug, Parser)]
-#[command(version)]
+#[command(version, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// URL to search for.
-    url: String,
-    #[arg(short, long)]
+    #[arg(required_unless_present = "resume")]
+    url: Option<String>,
     /// print the list of found snapshots (will not download).
-    list: Option<bool>,
+    #[arg(short, long, default_value_t = false, conflicts_with = "resume")]
+    list: bool,
+    /// how to print --list.
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
     /// only consider 'unique' versions of duplicate files.
     /// (this is done by filtering out adjacent results that are duplicates...
     /// the showDupeCount=true doesn't appear to work anymore...)
@@ -28,42 +52,257 @@ pub struct Args {
     /// format is YYYYMMDDhhmmss. you can omit trailing digits.
     #[arg(short, long, value_parser = validate_user_timestamp)]
     to_date: Option<String>,
-    /// limit the number of results
+    /// what url means: just that url (exact), everything under it (prefix),
+    /// everything on its host (host), or its host and subdomains (domain).
+    #[arg(long, value_enum)]
+    match_type: Option<MatchType>,
+    /// only rows where a cdx field matches a regex, field:regex, or !field:regex
+    /// for rows that don't. repeatable. e.g. --filter 'original:.*\.pdf$'
+    #[arg(long, value_parser = cdx::validate_filter)]
+    filter: Vec<String>,
+    /// drop rows whose field matches the row before it, field or field:N to only
+    /// compare the first N characters. repeatable.
+    #[arg(long, value_parser = cdx::validate_collapse)]
+    collapse: Vec<String>,
+    /// only this http status (a regex, so 2.. works too). repeatable.
+    #[arg(long)]
+    status: Vec<String>,
+    /// only this mime type (a regex, so image/.* works too). repeatable.
     #[arg(long)]
-    limit: Option<usize>,
+    mime: Vec<String>,
+    /// cdx fields to ask for, must include original and timestamp.
+    #[arg(long, value_delimiter = ',', value_parser = cdx::validate_field)]
+    fields: Vec<String>,
+    /// rows per request to cdx (default 5000 when following resume keys).
+    #[arg(long)]
+    page_size: Option<usize>,
+    /// stop after this many results, however many requests that takes.
+    #[arg(long, alias = "limit")]
+    max_results: Option<usize>,
+    /// page through results with cdx's page/showNumPages instead of resume keys.
+    #[arg(long, default_value_t = false)]
+    pages: bool,
+    /// directory to download into, files go in <dir>/<host>/<timestamp>/<path>.
+    /// the job's manifest goes in there too.
+    #[arg(short, long, default_value = ".")]
+    dir: PathBuf,
+    /// pick up an interrupted job in this directory, without asking cdx again.
+    #[arg(long, conflicts_with = "url")]
+    resume: Option<PathBuf>,
+    /// number of downloads to run at once.
+    #[arg(short, long, default_value_t = 4)]
+    concurrency: usize,
+    /// most requests per second to send to the wayback machine, across all downloads.
+    /// archive.org throttles hard, so be nice.
+    #[arg(long, default_value_t = 2.0)]
+    rate: f64,
+    /// how many times to retry a download after a 429, 5xx, or dropped connection.
+    #[arg(long, default_value_t = 5)]
+    retries: u32,
+    /// write each capture to its own file, or into WARC files with its timestamp,
+    /// url and archived headers. WARC always gets the original bytes, as with --raw.
+    #[arg(long, value_enum, default_value_t = Output::Files)]
+    output: Output,
+    /// gzip each WARC record (.warc.gz).
+    #[arg(long, default_value_t = false)]
+    gzip: bool,
+    /// keep cdx responses and snapshots in this directory and reuse them on later
+    /// runs. safe to share between jobs and people.
+    #[arg(long, global = true)]
+    cache: Option<PathBuf>,
+    /// don't talk to archive.org at all, everything has to come from --cache.
+    #[arg(long, global = true, requires = "cache", default_value_t = false)]
+    offline: bool,
+    /// once downloaded, point links in the html and css at the local copies
+    /// (closest in time), so the mirror can be browsed off disk. edits the files.
+    #[arg(long, default_value_t = false)]
+    rewrite_links: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// show what changed in a page between two captures.
+    Diff(DiffArgs),
+    /// how many captures a url has over time, by status and by mime type.
+    Stats(StatsArgs),
+    /// check the files in a download directory against their cdx digests.
+    Verify {
+        /// a directory something was downloaded into.
+        dir: PathBuf,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct DiffArgs {
+    /// URL of the page.
+    url: String,
+    /// timestamp of the first capture, YYYYMMDDhhmmss. you can omit trailing digits,
+    /// the closest capture is used. with --changes, where to start.
+    #[arg(value_parser = validate_user_timestamp)]
+    ts1: Option<String>,
+    /// timestamp of the second capture. with --changes, where to stop.
+    #[arg(value_parser = validate_user_timestamp)]
+    ts2: Option<String>,
+    /// walk every unique capture instead, reporting the ones where the content
+    /// really changed.
+    #[arg(long, default_value_t = false)]
+    changes: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct StatsArgs {
+    /// URL to look up.
+    url: String,
+    /// timestamp of earliest snapshot to consider.
+    #[arg(short, long, value_parser = validate_user_timestamp)]
+    from_date: Option<String>,
+    /// timestamp of most recent snapshot to consider.
+    #[arg(short, long, value_parser = validate_user_timestamp)]
+    to_date: Option<String>,
+    /// what url means, see the main --match-type.
+    #[arg(long, value_enum)]
+    match_type: Option<MatchType>,
+    /// captures per month instead of per year.
+    #[arg(long, default_value_t = false)]
+    monthly: bool,
+    /// print json instead of histograms.
+    #[arg(long, default_value_t = false)]
+    json: bool,
 }
 
 fn validate_user_timestamp(arg: &str) -> Result<String> {
     // not going to go nuts on validations yet, plus wayback is fine with 20201
-    anyhow::ensure!(arg.chars().all(|c| c.is_ascii_digit()) == true, "");
+    anyhow::ensure!(arg.chars().all(|c| c.is_ascii_digit()), "");
     anyhow::ensure!(arg.len() <= 14, "format is YYYYMMDDhhmmss");
     Ok(arg.to_owned())
 }
 
-pub struct WaybackClient {
-    client: reqwest::blocking::Client,
+// flags that only go with some outputs, output being the job's own when resuming
+fn check_output(args: &Args, output: Output) -> Result<()> {
+    if args.gzip && output != Output::Warc {
+        return Err(anyhow!("--gzip is for --output warc"));
+    }
+    if args.rewrite_links && output == Output::Warc {
+        return Err(anyhow!("--rewrite-links only works on loose files, not warc"));
+    }
+    Ok(())
 }
 
-impl WaybackClient {
-    fn new() -> Self {
-        WaybackClient{ client: reqwest::blocking::Client::new()}
+pub fn run(args: Args) -> Result<()> {
+    if args.resume.is_none() {
+        check_output(&args, args.output)?;
     }
+    let cache = args.cache.as_deref().map(|dir| Cache::new(dir).offline(args.offline));
+    match args.command {
+        Some(Command::Diff(diff)) => return run_diff(diff, cache),
+        Some(Command::Stats(stats)) => return run_stats(stats, cache),
+        Some(Command::Verify { dir }) => {
+            let report = verify::verify_dir(&dir)?;
+            println!("{}", report);
+            if !report.problems.is_empty() {
+                return Err(anyhow!("{} files failed verification", report.problems.len()));
+            }
+            return Ok(());
+        },
+        None => {},
+    }
+    let mut manifest = match (&args.resume, args.url.clone()) {
+        (Some(dir), _) => {
+            let manifest = Manifest::load(dir)?;
+            check_output(&args, manifest.query.output)?;
+            eprintln!("resuming {}, {} of {} left", manifest.query.cdx.url, manifest.remaining(), manifest.entries.len());
+            manifest
+        },
+        (None, Some(url)) if !url.is_empty() => {
+            let mut filters = args.filter.clone();
+            filters.extend(args.status.iter().map(|s| format!("statuscode:{}", s)));
+            filters.extend(args.mime.iter().map(|m| format!("mimetype:{}", m)));
+            let cdx_query = CdxQuery {
+                url,
+                unique: args.unique,
+                from_date: args.from_date.clone(),
+                to_date: args.to_date.clone(),
+                limit: args.page_size,
+                match_type: args.match_type,
+                filters,
+                collapse: args.collapse.clone(),
+                fields: args.fields.clone(),
+            };
+            if !cdx_query.fields.is_empty() {
+                for needed in ["original", "timestamp"] {
+                    if !cdx_query.fields.iter().any(|f| f == needed) {
+                        return Err(anyhow!("--fields needs {}", needed));
+                    }
+                }
+            }
+            let paging = if args.pages { Paging::Pages } else { Paging::ResumeKey };
+            let cdx = CdxClient::new().paging(paging).max_results(args.max_results).cache(cache.clone());
+            if args.list {
+                return listing::write_list(&mut std::io::stdout().lock(), args.format, cdx.iter(&cdx_query));
+            }
+            let mut results = Vec::new();
+            for info in cdx.iter(&cdx_query) {
+                results.push(info?);
+                if results.len() % 10000 == 0 {
+                    eprintln!("{} results so far", results.len());
+                }
+            }
+            let query = Query {
+                cdx: cdx_query,
+                raw: args.raw,
+                max_results: args.max_results,
+                output: args.output,
+                gzip: args.gzip,
+            };
+            Manifest::create(&args.dir, query, results)?
+        },
+        _ => return Err(anyhow!("need a url")),
+    };
+    let client = WaybackClient::new()
+        .rate(args.rate)
+        .retries(args.retries, Duration::from_secs(1))
+        .cache(cache);
+    let summary = download::download_all(&client, &mut manifest, args.concurrency)?;
+    println!("{}", summary);
+    if args.rewrite_links {
+        println!("{}", rewrite::rewrite_links(&mut manifest)?);
+    }
+    Ok(())
 }
 
-pub fn run(args: Args) -> Result<()> {
-    if args.url.is_empty() {
-        return Err(anyhow!("need a url"));
+fn run_stats(args: StatsArgs, cache: Option<Cache>) -> Result<()> {
+    // every capture, duplicates and all, so unique digests mean something
+    let query = CdxQuery {
+        from_date: args.from_date,
+        to_date: args.to_date,
+        match_type: args.match_type,
+        ..CdxQuery::new(&args.url)
+    };
+    let rows = CdxClient::new().cache(cache).get_cdx(&query)?;
+    let stats = stats::Stats::new(&args.url, &rows).monthly(args.monthly);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print!("{}", stats);
     }
+    Ok(())
+}
 
-    let results = cdx::CdxClient::get_cdx(&args.url, args.unique, args.from_date, args.to_date, args.limit)?;
-    // if let Some(list) = args.list {
-    //     if list {
-    //         for info in results {
-    //             println!("{:?}", info);
-    //         }
-    //     }
-    // }
-    println!("{:?}", results);
+fn run_diff(args: DiffArgs, cache: Option<Cache>) -> Result<()> {
+    let client = WaybackClient::new().cache(cache.clone());
+    if args.changes {
+        let query = CdxQuery { from_date: args.ts1, to_date: args.ts2, ..CdxQuery::new(&args.url) };
+        let changes = diff::changes(&CdxClient::new().cache(cache), &client, &query)?;
+        for change in &changes {
+            println!("{}", change);
+        }
+        println!("{} of {} changes were material", changes.iter().filter(|c| c.is_material()).count(), changes.len());
+        return Ok(());
+    }
+    let (Some(ts1), Some(ts2)) = (args.ts1, args.ts2) else {
+        return Err(anyhow!("need two timestamps to diff, or --changes"));
+    };
+    print!("{}", diff::diff_captures(&client, &args.url, &ts1, &ts2)?);
     Ok(())
 }
 
@@ -100,4 +339,29 @@ mod tests {
     fn test_validate_user_timestamp_fail() {
         _ = validate_user_timestamp("10/31/200").expect("want valid date");
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_output_flags() {
+        let err = |args: &[&str]| run(Args::parse_from(["wayback_downloads"].iter().chain(args))).unwrap_err().to_string();
+        // caught before asking cdx for anything
+        assert_eq!("--gzip is for --output warc", err(&["davemolk.com", "--gzip"]));
+        assert_eq!("--rewrite-links only works on loose files, not warc", err(&["davemolk.com", "--output", "warc", "--rewrite-links"]));
+        // a resumed job goes by its own output
+        let dir = std::env::temp_dir().join(format!("wayback_lib_output_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let query = Query { cdx: CdxQuery::new("davemolk.com"), output: Output::Warc, gzip: true, ..Default::default() };
+        Manifest::create(&dir, query, Vec::new()).unwrap();
+        let resume = dir.to_str().unwrap();
+        run(Args::parse_from(["wayback_downloads", "--resume", resume, "--gzip"])).unwrap();
+        assert_eq!("--rewrite-links only works on loose files, not warc", err(&["--resume", resume, "--rewrite-links"]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_limit_is_max_results() {
+        // --limit has always meant how many results in all
+        let args = Args::parse_from(["wayback_downloads", "davemolk.com", "--limit", "50"]);
+        assert_eq!(Some(50), args.max_results);
+        assert_eq!(None, args.page_size);
+        let args = Args::parse_from(["wayback_downloads", "davemolk.com", "--page-size", "100", "--max-results", "50"]);
+        assert_eq!((Some(100), Some(50)), (args.page_size, args.max_results));
+    }
+}
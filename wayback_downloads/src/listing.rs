@@ -0,0 +1,183 @@
+// --list, cdx results printed instead of downloaded
+use anyhow::Result;
+use std::io::Write;
+
+use crate::cdx::CdxInfo;
+
+const COLUMNS: [&str; 6] = ["original", "archived_at", "status_code", "mime_type", "digest", "length"];
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+    Jsonl,
+}
+
+/// writes every row in format. csv and jsonl go out as the rows come in, so a
+/// big query can be piped somewhere without waiting for the whole thing.
+pub fn write_list(out: &mut dyn Write, format: Format, rows: impl Iterator<Item = Result<CdxInfo>>) -> Result<()> {
+    match format {
+        Format::Table => write_table(out, &rows.collect::<Result<Vec<_>>>()?),
+        Format::Json => {
+            let rows = rows.collect::<Result<Vec<_>>>()?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&rows)?)?;
+            Ok(())
+        },
+        Format::Jsonl => {
+            for row in rows {
+                writeln!(out, "{}", serde_json::to_string(&row?)?)?;
+            }
+            Ok(())
+        },
+        Format::Csv => {
+            writeln!(out, "{}", COLUMNS.join(","))?;
+            for row in rows {
+                let row = row?;
+                let fields = [
+                    row.original.clone(),
+                    row.archived_at.format(TIME_FORMAT).to_string(),
+                    status(&row, ""),
+                    row.mime_type.clone(),
+                    row.digest.clone(),
+                    row.length.to_string(),
+                ];
+                writeln!(out, "{}", fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","))?;
+            }
+            Ok(())
+        },
+    }
+}
+
+fn write_table(out: &mut dyn Write, rows: &[CdxInfo]) -> Result<()> {
+    let cells = rows.iter()
+        .map(|row| [
+            row.original.clone(),
+            row.archived_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            status(row, "-"),
+            row.mime_type.clone(),
+            row.digest.clone(),
+            human_size(row.length),
+        ])
+        .collect::<Vec<_>>();
+    let mut widths = COLUMNS.map(|c| c.len());
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: &[String]| {
+        cells.iter().zip(widths).enumerate()
+            .map(|(i, (cell, width))| match i {
+                // sizes read better lined up on the right
+                5 => format!("{:>width$}", cell),
+                _ => format!("{:<width$}", cell),
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+    writeln!(out, "{}", line(&COLUMNS.map(String::from)))?;
+    for row in &cells {
+        writeln!(out, "{}", line(row))?;
+    }
+    let total = rows.iter().map(|r| r.length).sum::<u64>();
+    let mut digests = rows.iter().map(|r| r.digest.as_str()).collect::<Vec<_>>();
+    digests.sort();
+    digests.dedup();
+    writeln!(
+        out,
+        "{} snapshot{} ({} unique), {} total",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" },
+        digests.len(),
+        human_size(total),
+    )?;
+    Ok(())
+}
+
+fn status(row: &CdxInfo, unknown: &str) -> String {
+    row.status_code.map(|s| s.to_string()).unwrap_or(unknown.to_owned())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// bytes as B, KB, MB and so on, with one decimal past bytes.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<CdxInfo> {
+        vec![
+            CdxInfo::parse_row("http://davemolk.com/", "20150925144711", "301", "unk", "3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ", "414").unwrap(),
+            CdxInfo::parse_row("http://davemolk.com/a,b", "20231031041854", "-", "text/html", "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567", "1536").unwrap(),
+        ]
+    }
+    fn render(format: Format) -> String {
+        let mut out = Vec::new();
+        write_list(&mut out, format, rows().into_iter().map(Ok)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!("0 B", human_size(0));
+        assert_eq!("1023 B", human_size(1023));
+        assert_eq!("1.5 KB", human_size(1536));
+        assert_eq!("3.0 GB", human_size(3 * 1024 * 1024 * 1024));
+    }
+    #[test]
+    fn test_table() {
+        let want = "\
+original                 archived_at          status_code  mime_type  digest                            length
+http://davemolk.com/     2015-09-25 14:47:11  301          unk        3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ   414 B
+http://davemolk.com/a,b  2023-10-31 04:18:54  -            text/html  ABCDEFGHIJKLMNOPQRSTUVWXYZ234567  1.5 KB
+2 snapshots (2 unique), 1.9 KB total
+";
+        assert_eq!(want, render(Format::Table));
+    }
+    #[test]
+    fn test_csv() {
+        let want = "\
+original,archived_at,status_code,mime_type,digest,length
+http://davemolk.com/,2015-09-25T14:47:11,301,unk,3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ,414
+\"http://davemolk.com/a,b\",2023-10-31T04:18:54,,text/html,ABCDEFGHIJKLMNOPQRSTUVWXYZ234567,1536
+";
+        assert_eq!(want, render(Format::Csv));
+    }
+    #[test]
+    fn test_json_and_jsonl() {
+        let json: Vec<CdxInfo> = serde_json::from_str(&render(Format::Json)).unwrap();
+        assert_eq!(rows(), json);
+        let jsonl = render(Format::Jsonl);
+        let lines = jsonl.lines().collect::<Vec<_>>();
+        assert_eq!(2, lines.len());
+        assert_eq!(
+            r#"{"original":"http://davemolk.com/","archived_at":"2015-09-25T14:47:11","status_code":301,"mime_type":"unk","digest":"3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ","length":414}"#,
+            lines[0],
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cdx::{CdxInfo, CdxQuery};
+use crate::download::Output;
+
+pub const MANIFEST_FILE: &str = ".wayback_manifest.json";
+
+/// what was asked of cdx, so a resumed job doesn't need the original args.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Query {
+    #[serde(flatten)]
+    pub cdx: CdxQuery,
+    pub raw: bool,
+    #[serde(default)]
+    pub max_results: Option<usize>,
+    #[serde(default)]
+    pub output: Output,
+    // each warc record gzipped
+    #[serde(default)]
+    pub gzip: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pending,
+    Done,
+    // nothing worth saving, e.g. redirects and revisits
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub info: CdxInfo,
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    // of what ended up on disk, which only matches the cdx digest for raw downloads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_digest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // links were pointed at the local mirror, so the file is no longer what was downloaded
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rewritten: bool,
+}
+
+/// every row of a download job and how far along it is, kept in the download
+/// directory so an interrupted job can pick up where it left off.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub query: Query,
+    pub entries: Vec<Entry>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl Manifest {
+    /// starts a new job in dir. refuses to clobber a job that isn't finished.
+    pub fn create(dir: &Path, query: Query, results: Vec<CdxInfo>) -> Result<Self> {
+        if let Ok(existing) = Manifest::load(dir) {
+            if existing.remaining() > 0 {
+                return Err(anyhow!(
+                    "{} has an unfinished job for {}, use --resume {} or pick another --dir",
+                    dir.display(), existing.query.cdx.url, dir.display(),
+                ));
+            }
+        }
+        let entries = results.into_iter()
+            .map(|info| Entry { info, status: Status::Pending, bytes: None, file_digest: None, error: None, rewritten: false })
+            .collect();
+        let manifest = Manifest { query, entries, dir: dir.to_owned() };
+        manifest.save()?;
+        Ok(manifest)
+    }
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let data = fs::read_to_string(&path).map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+        let mut manifest: Manifest = serde_json::from_str(&data)?;
+        manifest.dir = dir.to_owned();
+        Ok(manifest)
+    }
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    /// writes to a temp file first, so getting killed mid-save doesn't lose the job.
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(MANIFEST_FILE);
+        let tmp = self.dir.join(format!("{}.tmp", MANIFEST_FILE));
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+    /// indexes of the entries still to do, failures get another go.
+    pub fn todo(&self) -> Vec<usize> {
+        self.entries.iter()
+            .enumerate()
+            .filter(|(_, e)| matches!(e.status, Status::Pending | Status::Failed))
+            .map(|(i, _)| i)
+            .collect()
+    }
+    pub fn remaining(&self) -> usize {
+        self.todo().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query() -> Query {
+        Query { cdx: CdxQuery { unique: true, ..CdxQuery::new("davemolk.com") }, raw: true, max_results: None, ..Default::default() }
+    }
+    fn results() -> Vec<CdxInfo> {
+        let file = fs::read_to_string("tests/cdx.json").unwrap();
+        let json: Vec<Vec<String>> = serde_json::from_str(&file).unwrap();
+        json[1..4].iter().map(|r| CdxInfo::parse_row(&r[0], &r[1], &r[2], &r[3], &r[4], &r[5]).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let dir = std::env::temp_dir().join(format!("wayback_manifest_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut manifest = Manifest::create(&dir, query(), results()).unwrap();
+        assert_eq!(vec![0, 1, 2], manifest.todo());
+        manifest.entries[0].status = Status::Done;
+        manifest.entries[0].bytes = Some(10);
+        manifest.entries[1].status = Status::Failed;
+        manifest.entries[1].error = Some("boom".to_string());
+        manifest.save().unwrap();
+
+        let loaded = Manifest::load(&dir).unwrap();
+        assert_eq!(query(), loaded.query);
+        assert_eq!(manifest.entries, loaded.entries);
+        assert_eq!(vec![1, 2], loaded.todo());
+        // an unfinished job can't be started over by accident
+        assert!(Manifest::create(&dir, query(), results()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,389 @@
+// points links in downloaded html and css at the local copies, so a mirror
+// can be browsed straight off disk
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::cdx::{parse_timestamp, CdxInfo};
+use crate::download::{digest, local_path};
+use crate::manifest::{Manifest, Status};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Rewritten {
+    pub files: usize,
+    pub links: usize,
+}
+
+impl fmt::Display for Rewritten {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rewrote {} links in {} files", self.links, self.files)
+    }
+}
+
+// every capture on disk of a url, keyed by host/path?query
+struct Mirror {
+    dir: PathBuf,
+    captures: HashMap<String, Vec<(NaiveDateTime, PathBuf)>>,
+}
+
+impl Mirror {
+    fn new(manifest: &Manifest) -> Self {
+        let mut captures: HashMap<String, Vec<(NaiveDateTime, PathBuf)>> = HashMap::new();
+        for entry in manifest.entries.iter().filter(|e| e.status == Status::Done) {
+            let (Ok(url), Ok(path)) = (Url::parse(&entry.info.original), local_path(manifest.dir(), &entry.info)) else {
+                continue;
+            };
+            if let Some(key) = key(&url) {
+                captures.entry(key).or_default().push((entry.info.archived_at, path));
+            }
+        }
+        Mirror { dir: manifest.dir().to_owned(), captures }
+    }
+    // the capture of url closest in time to at
+    fn closest(&self, url: &Url, at: NaiveDateTime) -> Option<&Path> {
+        self.captures.get(&key(url)?)?
+            .iter()
+            .min_by_key(|(archived_at, _)| (*archived_at - at).num_seconds().abs())
+            .map(|(_, path)| path.as_path())
+    }
+}
+
+/// rewrites links in every html and css file the manifest has on disk. links to
+/// anything that was captured point at the capture closest in time to the page,
+/// and anything else is left alone.
+pub fn rewrite_links(manifest: &mut Manifest) -> Result<Rewritten> {
+    let mirror = Mirror::new(manifest);
+    let mut out = Rewritten::default();
+    for entry in manifest.entries.iter_mut().filter(|e| e.status == Status::Done) {
+        let Some(kind) = Kind::of(&entry.info) else {
+            continue;
+        };
+        let path = local_path(&mirror.dir, &entry.info)?;
+        // not text, so nothing to rewrite
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let (rewritten, links) = rewrite(&text, kind, &entry.info, &path, &mirror);
+        if links > 0 {
+            fs::write(&path, &rewritten)?;
+            // so verify can still tell an edited file from a corrupted one
+            entry.rewritten = true;
+            entry.bytes = Some(rewritten.len() as u64);
+            entry.file_digest = Some(digest(rewritten.as_bytes()));
+            out.files += 1;
+            out.links += links;
+        }
+    }
+    manifest.save()?;
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Html,
+    Css,
+}
+
+impl Kind {
+    fn of(info: &CdxInfo) -> Option<Kind> {
+        let path = Url::parse(&info.original).map(|u| u.path().to_lowercase()).unwrap_or_default();
+        if info.mime_type.starts_with("text/html") || info.mime_type.starts_with("application/xhtml")
+            || path.ends_with('/') || path.ends_with(".html") || path.ends_with(".htm") {
+            Some(Kind::Html)
+        } else if info.mime_type.starts_with("text/css") || path.ends_with(".css") {
+            Some(Kind::Css)
+        } else {
+            None
+        }
+    }
+}
+
+fn rewrite(text: &str, kind: Kind, page: &CdxInfo, page_path: &Path, mirror: &Mirror) -> (String, usize) {
+    let mut links = 0;
+    let mut replace = |link: &str| {
+        let local = local_link(link, page, page_path, mirror);
+        if local.is_some() {
+            links += 1;
+        }
+        local
+    };
+    let text = match kind {
+        Kind::Html => rewrite_attributes(text, &mut replace),
+        Kind::Css => text.to_owned(),
+    };
+    // css files, <style> blocks and style attributes
+    let text = rewrite_css(&text, &mut replace);
+    (text, links)
+}
+
+// the values of href= and src= attributes
+fn rewrite_attributes(text: &str, replace: &mut impl FnMut(&str) -> Option<String>) -> String {
+    let lower = text.to_ascii_lowercase();
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    while let Some((start, name_len)) = next_attribute(&lower, pos) {
+        let value_start = start + name_len;
+        let rest = &text[value_start..];
+        let (quote, value_len) = match rest.chars().next() {
+            Some(q @ ('"' | '\'')) => (1, rest[1..].find(q).unwrap_or(rest.len() - 1)),
+            _ => (0, rest.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(rest.len())),
+        };
+        let value = &rest[quote..quote + value_len];
+        out.push_str(&text[pos..value_start + quote]);
+        match replace(&decode(value)) {
+            Some(local) => out.push_str(&local),
+            None => out.push_str(value),
+        }
+        pos = value_start + quote + value_len;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
+
+// where the next href= or src= is, and how long the name and = are
+fn next_attribute(lower: &str, from: usize) -> Option<(usize, usize)> {
+    ["href=", "src="].iter()
+        .filter_map(|name| {
+            let mut at = from;
+            while let Some(i) = lower[at..].find(name) {
+                let start = at + i;
+                // has to be its own attribute, not the end of data-src= or similar
+                if lower[..start].ends_with(|c: char| c.is_whitespace()) {
+                    return Some((start, name.len()));
+                }
+                at = start + name.len();
+            }
+            None
+        })
+        .min()
+}
+
+// url(...) and @import "..."
+fn rewrite_css(text: &str, replace: &mut impl FnMut(&str) -> Option<String>) -> String {
+    let lower = text.to_ascii_lowercase();
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    loop {
+        let next = [lower[pos..].find("url("), lower[pos..].find("@import")].into_iter().flatten().min();
+        let Some(i) = next else {
+            break;
+        };
+        let start = pos + i;
+        let after = if lower[start..].starts_with("url(") { start + 4 } else { start + 7 };
+        let rest = &text[after..];
+        let skip = rest.len() - rest.trim_start().len();
+        let rest = &rest[skip..];
+        let (quote, value_len) = match rest.chars().next() {
+            Some(q @ ('"' | '\'')) => (1, rest[1..].find(q).unwrap_or(rest.len() - 1)),
+            // @import url(...) is handled by the next time round
+            _ if lower[start..].starts_with("@import") => (0, 0),
+            _ => (0, rest.find(|c: char| c == ')' || c.is_whitespace()).unwrap_or(rest.len())),
+        };
+        let value_start = after + skip + quote;
+        let value = &text[value_start..value_start + value_len];
+        out.push_str(&text[pos..value_start]);
+        match replace(value).filter(|_| value_len > 0) {
+            Some(local) => out.push_str(&local),
+            None => out.push_str(value),
+        }
+        pos = value_start + value_len;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
+
+// a link as it appears in a page to the relative path of its closest capture
+fn local_link(link: &str, page: &CdxInfo, page_path: &Path, mirror: &Mirror) -> Option<String> {
+    let link = link.trim();
+    let lower = link.to_ascii_lowercase();
+    if link.is_empty() || link.starts_with('#') || ["data:", "javascript:", "mailto:", "tel:"].iter().any(|s| lower.starts_with(s)) {
+        return None;
+    }
+    let (link, fragment) = match link.split_once('#') {
+        Some((link, fragment)) => (link, Some(fragment)),
+        None => (link, None),
+    };
+    let (url, at) = match unwrap_wayback(link) {
+        Some((original, at)) => (Url::parse(&original).ok()?, at.unwrap_or(page.archived_at)),
+        None => (Url::parse(&page.original).ok()?.join(link).ok()?, page.archived_at),
+    };
+    let target = mirror.closest(&url, at)?;
+    let mut local = relative(page_path.strip_prefix(&mirror.dir).ok()?, target.strip_prefix(&mirror.dir).ok()?)?;
+    if let Some(fragment) = fragment {
+        local.push('#');
+        local.push_str(fragment);
+    }
+    Some(local)
+}
+
+/// the original url and capture time out of a wayback link, like
+/// http://web.archive.org/web/20150925144711im_/http://davemolk.com/a.png
+/// or the root relative /web/20150925144711/http://davemolk.com/ the wayback
+/// machine puts in pages it serves.
+fn unwrap_wayback(link: &str) -> Option<(String, Option<NaiveDateTime>)> {
+    let lower = link.to_ascii_lowercase();
+    let (rest, on_wayback) = match ["https://web.archive.org", "http://web.archive.org", "//web.archive.org"]
+        .iter()
+        .find(|prefix| lower.starts_with(*prefix))
+    {
+        Some(prefix) => (&link[prefix.len()..], true),
+        None => (link, false),
+    };
+    let rest = rest.strip_prefix("/web/")?;
+    let (stamp, original) = rest.split_once('/')?;
+    let digits = stamp.chars().take_while(|c| c.is_ascii_digit()).collect::<String>();
+    // flags like id_ or im_ come after the timestamp
+    if digits.is_empty() || digits.len() > 14 || !stamp[digits.len()..].chars().all(|c| c.is_ascii_alphabetic() || c == '_') {
+        return None;
+    }
+    let has_scheme = original.starts_with("http://") || original.starts_with("https://");
+    // a site can have its own /web/123/ paths, so only trust root relative links
+    // that clearly wrap another url
+    if !has_scheme && !on_wayback {
+        return None;
+    }
+    let original = if has_scheme { original.to_owned() } else { format!("http://{}", original.trim_start_matches('/')) };
+    let at = parse_timestamp(&digits).ok();
+    Some((original, at))
+}
+
+// what urls are looked up by, so http/https and default ports don't matter
+fn key(url: &Url) -> Option<String> {
+    let mut key = url.host_str()?.to_owned();
+    if let Some(port) = url.port() {
+        key.push_str(&format!(":{}", port));
+    }
+    key.push_str(url.path());
+    if let Some(query) = url.query() {
+        key.push('?');
+        key.push_str(query);
+    }
+    Some(key)
+}
+
+/// the link from one file to another, both relative to the same directory.
+fn relative(from: &Path, to: &Path) -> Option<String> {
+    let names = |p: &Path| p.components()
+        .map(|c| match c {
+            Component::Normal(name) => name.to_str().map(String::from),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let from = names(from.parent()?)?;
+    let to = names(to)?;
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec!["..".to_owned(); from.len() - common];
+    parts.extend(to[common..].iter().map(|name| encode(name)));
+    Some(parts.join("/"))
+}
+
+// file names can have characters that mean something in a link
+fn encode(name: &str) -> String {
+    name.replace('%', "%25")
+        .replace('?', "%3F")
+        .replace('#', "%23")
+        .replace(' ', "%20")
+        .replace('"', "%22")
+}
+
+fn decode(value: &str) -> String {
+    value.replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdx::{CdxQuery, DATE_FORMAT};
+    use crate::manifest::Query;
+
+    fn info(original: &str, timestamp: &str, mime: &str) -> CdxInfo {
+        CdxInfo::parse_row(original, timestamp, "200", mime, "-", "0").unwrap()
+    }
+
+    #[test]
+    fn test_relative() {
+        let from = Path::new("davemolk.com/20150925144711/index.html");
+        assert_eq!(Some("style.css".to_string()), relative(from, Path::new("davemolk.com/20150925144711/style.css")));
+        assert_eq!(
+            Some("../20140101000000/img/a%3Fv=1".to_string()),
+            relative(from, Path::new("davemolk.com/20140101000000/img/a?v=1")),
+        );
+        assert_eq!(
+            Some("../../cdn.com/20150925144711/x.js".to_string()),
+            relative(from, Path::new("cdn.com/20150925144711/x.js")),
+        );
+    }
+    #[test]
+    fn test_unwrap_wayback() {
+        let at = |s| NaiveDateTime::parse_from_str(s, DATE_FORMAT).ok();
+        assert_eq!(
+            Some(("http://davemolk.com/a.png".to_string(), at("20150925144711"))),
+            unwrap_wayback("http://web.archive.org/web/20150925144711im_/http://davemolk.com/a.png"),
+        );
+        assert_eq!(
+            Some(("https://davemolk.com/".to_string(), at("20150101000000"))),
+            unwrap_wayback("/web/2015/https://davemolk.com/"),
+        );
+        assert_eq!(
+            Some(("http://davemolk.com/".to_string(), at("20150925144711"))),
+            unwrap_wayback("//web.archive.org/web/20150925144711/davemolk.com/"),
+        );
+        assert_eq!(None, unwrap_wayback("/web/2015/about"));
+        assert_eq!(None, unwrap_wayback("http://davemolk.com/web/2015/"));
+    }
+    #[test]
+    fn test_rewrite_links() {
+        let dir = std::env::temp_dir().join(format!("wayback_rewrite_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let infos = vec![
+            info("http://davemolk.com/", "20150925144711", "text/html"),
+            info("http://davemolk.com/style.css", "20150925144711", "text/css"),
+            info("http://davemolk.com/bg.png", "20140101000000", "image/png"),
+            info("http://davemolk.com/bg.png", "20150901000000", "image/png"),
+            info("http://davemolk.com/bg.png", "20200101000000", "image/png"),
+            info("http://davemolk.com/about?x=1", "20160101000000", "text/html"),
+        ];
+        let query = Query { cdx: CdxQuery::new("davemolk.com"), raw: true, max_results: None, ..Default::default() };
+        let mut manifest = Manifest::create(&dir, query, infos.clone()).unwrap();
+        for entry in manifest.entries.iter_mut() {
+            entry.status = Status::Done;
+        }
+        let page = concat!(
+            r#"<link rel="stylesheet" href="/web/20150925144711cs_/http://davemolk.com/style.css">"#,
+            r#"<a HREF='about?x=1#top'>about</a> <img data-src="bg.png" src=http://davemolk.com/bg.png>"#,
+            r#"<a href="https://elsewhere.com/">gone</a><a href="mailto:me@davemolk.com">mail</a>"#,
+            r#"<div style="background: url('https://web.archive.org/web/2020im_/http://davemolk.com/bg.png')"></div>"#,
+        );
+        let css = r#"@import "http://davemolk.com/missing.css"; body { background: url(bg.png) }"#;
+        let contents = [page, css, "png", "png", "png", "<a href=\"/\">home</a>"];
+        for (info, body) in infos.iter().zip(contents) {
+            let path = local_path(&dir, info).unwrap();
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, body).unwrap();
+        }
+
+        let got = rewrite_links(&mut manifest).unwrap();
+        assert_eq!(Rewritten { files: 3, links: 6 }, got);
+        assert!(manifest.entries[0].rewritten);
+        assert!(!manifest.entries[2].rewritten);
+        let page = fs::read_to_string(local_path(&dir, &infos[0]).unwrap()).unwrap();
+        assert_eq!(
+            concat!(
+                r#"<link rel="stylesheet" href="style.css">"#,
+                r#"<a HREF='../20160101000000/about%3Fx=1#top'>about</a> <img data-src="bg.png" src=../20150901000000/bg.png>"#,
+                r#"<a href="https://elsewhere.com/">gone</a><a href="mailto:me@davemolk.com">mail</a>"#,
+                r#"<div style="background: url('../20200101000000/bg.png')"></div>"#,
+            ),
+            page,
+        );
+        let css = fs::read_to_string(local_path(&dir, &infos[1]).unwrap()).unwrap();
+        assert_eq!(r#"@import "http://davemolk.com/missing.css"; body { background: url(../20150901000000/bg.png) }"#, css);
+        let about = fs::read_to_string(local_path(&dir, &infos[5]).unwrap()).unwrap();
+        assert_eq!("<a href=\"../20150925144711/index.html\">home</a>", about);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
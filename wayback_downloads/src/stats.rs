@@ -0,0 +1,144 @@
+// what the captures of a url look like over time, before deciding what to download
+use chrono::NaiveDateTime;
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use crate::cdx::CdxInfo;
+use crate::listing::human_size;
+
+// widest a histogram bar gets
+const BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Stats {
+    pub url: String,
+    pub captures: usize,
+    pub unique_digests: usize,
+    pub bytes: u64,
+    pub first_seen: Option<NaiveDateTime>,
+    pub last_seen: Option<NaiveDateTime>,
+    pub per_year: BTreeMap<String, usize>,
+    pub per_month: BTreeMap<String, usize>,
+    // "-" when cdx doesn't know
+    pub status_codes: BTreeMap<String, usize>,
+    pub mime_types: BTreeMap<String, usize>,
+    // only used for printing
+    #[serde(skip)]
+    pub monthly: bool,
+}
+
+impl Stats {
+    pub fn new(url: &str, rows: &[CdxInfo]) -> Self {
+        let mut stats = Stats { url: url.to_owned(), captures: rows.len(), ..Default::default() };
+        let mut digests = HashSet::new();
+        for row in rows {
+            digests.insert(row.digest.as_str());
+            stats.bytes += row.length;
+            stats.first_seen = Some(stats.first_seen.map_or(row.archived_at, |first| first.min(row.archived_at)));
+            stats.last_seen = stats.last_seen.max(Some(row.archived_at));
+            *stats.per_year.entry(row.archived_at.format("%Y").to_string()).or_default() += 1;
+            *stats.per_month.entry(row.archived_at.format("%Y-%m").to_string()).or_default() += 1;
+            let status = row.status_code.map(|s| s.to_string()).unwrap_or("-".to_owned());
+            *stats.status_codes.entry(status).or_default() += 1;
+            *stats.mime_types.entry(row.mime_type.clone()).or_default() += 1;
+        }
+        stats.unique_digests = digests.len();
+        stats
+    }
+    /// histogram by month rather than year when printed.
+    pub fn monthly(mut self, monthly: bool) -> Self {
+        self.monthly = monthly;
+        self
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} captures, {} unique digests, {} archived",
+            self.url, self.captures, self.unique_digests, human_size(self.bytes),
+        )?;
+        if let (Some(first), Some(last)) = (self.first_seen, self.last_seen) {
+            writeln!(f, "first seen {}, last seen {}", first, last)?;
+        }
+        let (title, periods) = if self.monthly {
+            ("captures per month", &self.per_month)
+        } else {
+            ("captures per year", &self.per_year)
+        };
+        histogram(f, title, periods.iter().map(|(k, v)| (k.as_str(), *v)).collect())?;
+        histogram(f, "status codes", by_count(&self.status_codes))?;
+        histogram(f, "mime types", by_count(&self.mime_types))
+    }
+}
+
+// biggest first, for breakdowns where the order of the keys means nothing
+fn by_count(counts: &BTreeMap<String, usize>) -> Vec<(&str, usize)> {
+    let mut out = counts.iter().map(|(k, v)| (k.as_str(), *v)).collect::<Vec<_>>();
+    out.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    out
+}
+
+fn histogram(f: &mut fmt::Formatter, title: &str, rows: Vec<(&str, usize)>) -> fmt::Result {
+    write!(f, "\n{}", title)?;
+    let label_width = rows.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0);
+    let max = rows.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    for (label, count) in rows {
+        // anything at all gets at least one #
+        let bar = (count * BAR_WIDTH).div_ceil(max.max(1));
+        write!(f, "\n  {:<label_width$}  {:<BAR_WIDTH$}  {}", label, "#".repeat(bar), count)?;
+    }
+    writeln!(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<CdxInfo> {
+        let file = std::fs::read_to_string("tests/cdx.json").unwrap();
+        let json: Vec<Vec<String>> = serde_json::from_str(&file).unwrap();
+        json[1..].iter().map(|r| CdxInfo::parse_row(&r[0], &r[1], &r[2], &r[3], &r[4], &r[5]).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_stats() {
+        let rows = rows();
+        let stats = Stats::new("davemolk.com", &rows);
+        assert_eq!(20, stats.captures);
+        assert_eq!(20, stats.per_year.values().sum::<usize>());
+        assert_eq!(20, stats.per_month.values().sum::<usize>());
+        assert_eq!(20, stats.status_codes.values().sum::<usize>());
+        assert_eq!(20, stats.mime_types.values().sum::<usize>());
+        assert!(stats.unique_digests <= 20);
+        assert_eq!(rows.iter().map(|r| r.archived_at).min(), stats.first_seen);
+        assert_eq!(rows.iter().map(|r| r.archived_at).max(), stats.last_seen);
+        assert_eq!(rows.iter().map(|r| r.length).sum::<u64>(), stats.bytes);
+    }
+    #[test]
+    fn test_histogram() {
+        let rows = [
+            CdxInfo::parse_row("http://davemolk.com/", "20150925144711", "200", "text/html", "A", "10").unwrap(),
+            CdxInfo::parse_row("http://davemolk.com/", "20150926144711", "200", "text/html", "A", "10").unwrap(),
+            CdxInfo::parse_row("http://davemolk.com/", "20150927144711", "200", "text/html", "B", "10").unwrap(),
+            CdxInfo::parse_row("http://davemolk.com/", "20150928144711", "200", "text/html", "B", "10").unwrap(),
+            CdxInfo::parse_row("http://davemolk.com/a", "20170101000000", "-", "warc/revisit", "B", "10").unwrap(),
+        ];
+        let stats = Stats::new("davemolk.com", &rows);
+        assert_eq!(2, stats.unique_digests);
+        let out = stats.to_string();
+        let full = "#".repeat(BAR_WIDTH);
+        let quarter = format!("{:<BAR_WIDTH$}", "#".repeat(BAR_WIDTH / 4));
+        assert!(out.starts_with("davemolk.com: 5 captures, 2 unique digests, 50 B archived\nfirst seen 2015-09-25 14:47:11, last seen 2017-01-01 00:00:00\n"));
+        assert!(out.contains(&format!("captures per year\n  2015  {}  4\n  2017  {}  1\n", full, quarter)));
+        assert!(out.contains(&format!("status codes\n  200  {}  4\n  -    {}  1\n", full, quarter)));
+        assert!(out.contains("mime types\n  text/html "));
+        let monthly = Stats::new("davemolk.com", &rows).monthly(true).to_string();
+        assert!(monthly.contains("captures per month\n  2015-09  "));
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(4, json["per_year"]["2015"]);
+        assert_eq!("2015-09-25T14:47:11", json["first_seen"]);
+    }
+}
@@ -0,0 +1,221 @@
+// checks a download directory against what cdx and the manifest say should be there
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::download::{digest, is_digest, local_path, Output};
+use crate::manifest::{Entry, Manifest, Status};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    /// matches the cdx digest.
+    Ok,
+    /// can't match the cdx digest for a reason wayback is known for, but it's
+    /// still what was downloaded.
+    Quirk(Quirk),
+    /// cdx didn't give a digest, but it's still what was downloaded.
+    Unverifiable,
+    Missing,
+    /// shorter than what was downloaded.
+    Truncated,
+    /// changed since it was downloaded.
+    Corrupt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quirk {
+    /// not downloaded raw (id_), so wayback rewrote it and the digest is of the original.
+    Rewritten,
+    /// --rewrite-links edited it.
+    LinksRewritten,
+    /// raw, but what wayback served still doesn't match the cdx digest.
+    Mismatched,
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Quirk::Rewritten => write!(f, "rewritten by the wayback machine (not raw)"),
+            Quirk::LinksRewritten => write!(f, "links rewritten for the local mirror"),
+            Quirk::Mismatched => write!(f, "served differently than cdx says (raw)"),
+        }
+    }
+}
+
+/// what to make of a file (None if it's not there) for a manifest entry.
+pub fn check(entry: &Entry, raw: bool, data: Option<&[u8]>) -> Verdict {
+    let Some(data) = data else {
+        return Verdict::Missing;
+    };
+    if entry.bytes.is_some_and(|bytes| (data.len() as u64) < bytes) {
+        return Verdict::Truncated;
+    }
+    let found = digest(data);
+    let unchanged = entry.file_digest.as_ref().map(|d| *d == found);
+    if unchanged == Some(false) {
+        return Verdict::Corrupt;
+    }
+    if entry.rewritten {
+        Verdict::Quirk(Quirk::LinksRewritten)
+    } else if !raw {
+        Verdict::Quirk(Quirk::Rewritten)
+    } else if !is_digest(&entry.info.digest) {
+        Verdict::Unverifiable
+    } else if found == entry.info.digest {
+        Verdict::Ok
+    } else {
+        Verdict::Quirk(Quirk::Mismatched)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub ok: usize,
+    pub quirks: Vec<(String, Quirk)>,
+    pub unverifiable: usize,
+    // path and what's wrong with it, only missing, truncated or corrupt
+    pub problems: Vec<(String, Verdict)>,
+}
+
+impl Report {
+    fn count(&self, verdict: Verdict) -> usize {
+        self.problems.iter().filter(|(_, v)| *v == verdict).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ok:             {}", self.ok)?;
+        writeln!(f, "wayback quirks: {}", self.quirks.len())?;
+        writeln!(f, "no digest:      {}", self.unverifiable)?;
+        writeln!(f, "missing:        {}", self.count(Verdict::Missing))?;
+        writeln!(f, "truncated:      {}", self.count(Verdict::Truncated))?;
+        writeln!(f, "corrupt:        {}", self.count(Verdict::Corrupt))?;
+        write!(f, "mismatched:     {}", self.quirks.iter().filter(|(_, q)| *q == Quirk::Mismatched).count())?;
+        for (path, verdict) in &self.problems {
+            write!(f, "\n  {}: {:?}", path, verdict)?;
+        }
+        if !self.quirks.is_empty() {
+            write!(f, "\nquirks, digests can't match but the files are as downloaded:")?;
+            for (path, quirk) in &self.quirks {
+                write!(f, "\n  {}: {}", path, quirk)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// recomputes the digest of every file the manifest in dir says was downloaded.
+pub fn verify_dir(dir: &Path) -> Result<Report> {
+    let manifest = Manifest::load(dir)?;
+    if manifest.query.output == Output::Warc {
+        return Err(anyhow!("{} was downloaded into warc files, only loose files can be verified", dir.display()));
+    }
+    let mut report = Report::default();
+    for entry in manifest.entries.iter().filter(|e| e.status == Status::Done) {
+        let path = local_path(dir, &entry.info)?;
+        let data = fs::read(&path).ok();
+        let name = path.strip_prefix(dir).unwrap_or(&path).display().to_string();
+        match check(entry, manifest.query.raw, data.as_deref()) {
+            Verdict::Ok => report.ok += 1,
+            Verdict::Unverifiable => report.unverifiable += 1,
+            Verdict::Quirk(quirk) => report.quirks.push((name, quirk)),
+            problem => report.problems.push((name, problem)),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdx::{CdxInfo, CdxQuery};
+    use crate::manifest::Query;
+
+    fn entry(body: &[u8], on_disk: &[u8]) -> Entry {
+        let info = CdxInfo::parse_row("http://davemolk.com/", "20150925144711", "200", "text/html", &digest(body), "999").unwrap();
+        Entry {
+            info,
+            status: Status::Done,
+            bytes: Some(on_disk.len() as u64),
+            file_digest: Some(digest(on_disk)),
+            error: None,
+            rewritten: false,
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let good = entry(b"home", b"home");
+        assert_eq!(Verdict::Ok, check(&good, true, Some(b"home")));
+        assert_eq!(Verdict::Missing, check(&good, true, None));
+        assert_eq!(Verdict::Truncated, check(&good, true, Some(b"ho")));
+        assert_eq!(Verdict::Corrupt, check(&good, true, Some(b"hoMe")));
+        // wayback served something else than cdx described
+        let served = entry(b"home", b"other");
+        assert_eq!(Verdict::Quirk(Quirk::Mismatched), check(&served, true, Some(b"other")));
+        // which is expected for anything not raw
+        assert_eq!(Verdict::Quirk(Quirk::Rewritten), check(&served, false, Some(b"other")));
+        assert_eq!(Verdict::Corrupt, check(&served, false, Some(b"otheR")));
+        let mut edited = entry(b"home", b"home, edited");
+        edited.rewritten = true;
+        assert_eq!(Verdict::Quirk(Quirk::LinksRewritten), check(&edited, true, Some(b"home, edited")));
+        let mut unknown = entry(b"home", b"home");
+        unknown.info.digest = "-".to_string();
+        assert_eq!(Verdict::Unverifiable, check(&unknown, true, Some(b"home")));
+    }
+    #[test]
+    fn test_verify_dir() {
+        let dir = std::env::temp_dir().join(format!("wayback_verify_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let query = Query { cdx: CdxQuery::new("davemolk.com"), raw: true, max_results: None, ..Default::default() };
+        let mut manifest = Manifest::create(&dir, query, Vec::new()).unwrap();
+        let files = [("a", b"a".as_slice(), b"a".as_slice()), ("b", b"b", b"b"), ("c", b"c", b"c"), ("d", b"d", b"x")];
+        for (name, body, on_disk) in files {
+            let mut e = entry(body, on_disk);
+            e.info.original = format!("http://davemolk.com/{}", name);
+            let path = local_path(&dir, &e.info).unwrap();
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, on_disk).unwrap();
+            manifest.entries.push(e);
+        }
+        manifest.save().unwrap();
+        fs::write(dir.join("davemolk.com/20150925144711/b"), "B").unwrap();
+        fs::remove_file(dir.join("davemolk.com/20150925144711/c")).unwrap();
+
+        let report = verify_dir(&dir).unwrap();
+        assert_eq!(1, report.ok);
+        assert_eq!(
+            vec![
+                ("davemolk.com/20150925144711/b".to_string(), Verdict::Corrupt),
+                ("davemolk.com/20150925144711/c".to_string(), Verdict::Missing),
+            ],
+            report.problems,
+        );
+        assert_eq!(vec![("davemolk.com/20150925144711/d".to_string(), Quirk::Mismatched)], report.quirks);
+        assert!(report.to_string().contains("corrupt:        1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_verify_mismatched_passes() {
+        use clap::Parser;
+        let dir = std::env::temp_dir().join(format!("wayback_verify_mismatched_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let query = Query { cdx: CdxQuery::new("davemolk.com"), raw: true, max_results: None, ..Default::default() };
+        let mut manifest = Manifest::create(&dir, query, Vec::new()).unwrap();
+        // all there, just not what cdx says
+        let e = entry(b"home", b"served");
+        let path = local_path(&dir, &e.info).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "served").unwrap();
+        manifest.entries.push(e);
+        manifest.save().unwrap();
+        let verify = || crate::run(crate::Args::parse_from(["wayback_downloads", "verify", dir.to_str().unwrap()]));
+        verify().unwrap();
+        assert!(verify_dir(&dir).unwrap().to_string().contains("mismatched:     1"));
+        fs::write(&path, "serve").unwrap();
+        assert_eq!("1 files failed verification", verify().unwrap_err().to_string());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+// captures written as WARC 1.1 records instead of loose files, so replay tools
+// get the original timestamp, url and headers along with the content
+use anyhow::Result;
+use chrono::Utc;
+use libflate::gzip;
+use sha1::{Digest, Sha1};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::cdx::CdxInfo;
+use crate::client::Headers;
+use crate::download::digest;
+
+// start a new file once one gets this big
+const MAX_FILE_SIZE: u64 = 1 << 30;
+const WARC_DATE: &str = "%Y-%m-%dT%H:%M:%SZ";
+// the wayback machine sends these, the rest of a capture's headers are the ones
+// it was archived with
+const ORIG_HEADER_PREFIX: &str = "x-archive-orig-";
+// describe the original transfer, not the body we have
+const DROPPED_HEADERS: [&str; 3] = ["content-length", "transfer-encoding", "content-encoding"];
+
+/// the headers a capture was archived with, out of a wayback response.
+pub fn archived_headers<'a>(headers: impl Iterator<Item = (&'a str, &'a str)>) -> Headers {
+    headers
+        .filter_map(|(name, value)| {
+            let name = name.to_ascii_lowercase();
+            let name = name.strip_prefix(ORIG_HEADER_PREFIX)?;
+            Some((name.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+/// writes wayback-00000.warc (.warc.gz with gzip), wayback-00001.warc and so on
+/// into a directory. a new writer never appends to an existing file, so a
+/// resumed job carries on in the next one.
+pub struct WarcWriter {
+    dir: PathBuf,
+    gzip: bool,
+    index: usize,
+    current: Option<(File, u64)>,
+    warcinfo_id: String,
+    // records written to the current file
+    records: u64,
+}
+
+impl WarcWriter {
+    pub fn new(dir: &Path, gzip: bool) -> Self {
+        WarcWriter { dir: dir.to_owned(), gzip, index: 0, current: None, warcinfo_id: String::new(), records: 0 }
+    }
+    /// a response record if the archived headers are known (they never are for
+    /// cached snapshots), otherwise a resource record of just the content.
+    pub fn write_capture(&mut self, info: &CdxInfo, headers: &[(String, String)], body: &[u8]) -> Result<()> {
+        let (kind, content_type, block) = if headers.is_empty() {
+            (
+                "resource",
+                if info.mime_type == "-" || info.mime_type == "unk" { "application/octet-stream".to_owned() } else { info.mime_type.clone() },
+                body.to_vec(),
+            )
+        } else {
+            ("response", "application/http;msgtype=response".to_owned(), http_response(info, headers, body))
+        };
+        let date = info.archived_at.format(WARC_DATE).to_string();
+        let warcinfo_id = self.warcinfo_id()?;
+        self.records += 1;
+        // the file's own id and where in it the record goes, so a capture written
+        // again after an interrupted job doesn't reuse an id
+        let id = record_id(&format!("{} {} {} {} {}", warcinfo_id, self.records, kind, info.original, date));
+        let fields = vec![
+            ("WARC-Type", kind.to_owned()),
+            ("WARC-Record-ID", id),
+            ("WARC-Date", date),
+            ("WARC-Target-URI", info.original.clone()),
+            ("WARC-Warcinfo-ID", warcinfo_id),
+            ("WARC-Payload-Digest", format!("sha1:{}", digest(body))),
+            ("WARC-Block-Digest", format!("sha1:{}", digest(&block))),
+            ("Content-Type", content_type),
+        ];
+        self.write_record(&fields, &block)
+    }
+    fn warcinfo_id(&mut self) -> Result<String> {
+        self.file()?;
+        Ok(self.warcinfo_id.clone())
+    }
+    // the file to write to, starting a new one (warcinfo record first) when needed
+    fn file(&mut self) -> Result<&mut (File, u64)> {
+        if self.current.as_ref().is_some_and(|(_, size)| *size >= MAX_FILE_SIZE) {
+            self.current = None;
+        }
+        if self.current.is_none() {
+            std::fs::create_dir_all(&self.dir)?;
+            let ext = if self.gzip { "warc.gz" } else { "warc" };
+            let (name, file) = loop {
+                let name = format!("wayback-{:05}.{}", self.index, ext);
+                self.index += 1;
+                match OpenOptions::new().write(true).create_new(true).open(self.dir.join(&name)) {
+                    Ok(file) => break (name, file),
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            };
+            let now = Utc::now();
+            let path = self.dir.canonicalize().unwrap_or(self.dir.clone()).join(&name);
+            self.warcinfo_id = record_id(&format!("warcinfo {} {}", path.display(), now.to_rfc3339()));
+            let now = now.format(WARC_DATE).to_string();
+            self.current = Some((file, 0));
+            self.records = 0;
+            let block = format!(
+                "software: wayback_downloads/{}\r\nformat: WARC File Format 1.1\r\nconformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n",
+                env!("CARGO_PKG_VERSION"),
+            );
+            let fields = vec![
+                ("WARC-Type", "warcinfo".to_owned()),
+                ("WARC-Record-ID", self.warcinfo_id.clone()),
+                ("WARC-Date", now),
+                ("WARC-Filename", name),
+                ("Content-Type", "application/warc-fields".to_owned()),
+            ];
+            self.write_record(&fields, block.as_bytes())?;
+        }
+        Ok(self.current.as_mut().unwrap())
+    }
+    fn write_record(&mut self, fields: &[(&str, String)], block: &[u8]) -> Result<()> {
+        let mut record = b"WARC/1.1\r\n".to_vec();
+        for (name, value) in fields {
+            record.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        record.extend_from_slice(format!("Content-Length: {}\r\n\r\n", block.len()).as_bytes());
+        record.extend_from_slice(block);
+        record.extend_from_slice(b"\r\n\r\n");
+        if self.gzip {
+            // each record its own gzip member, so tools can seek straight to one
+            let mut encoder = gzip::Encoder::new(Vec::new())?;
+            encoder.write_all(&record)?;
+            record = encoder.finish().into_result()?;
+        }
+        let (file, size) = self.file()?;
+        file.write_all(&record)?;
+        *size += record.len() as u64;
+        Ok(())
+    }
+}
+
+fn http_response(info: &CdxInfo, headers: &[(String, String)], body: &[u8]) -> Vec<u8> {
+    let status = info.status_code.unwrap_or(200);
+    let reason = reqwest::StatusCode::from_u16(status).ok().and_then(|s| s.canonical_reason()).unwrap_or("");
+    let mut out = format!("HTTP/1.1 {} {}\r\n", status, reason);
+    for (name, value) in headers.iter().filter(|(name, _)| !DROPPED_HEADERS.contains(&name.as_str())) {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str(&format!("content-length: {}\r\n\r\n", body.len()));
+    let mut out = out.into_bytes();
+    out.extend_from_slice(body);
+    out
+}
+
+// a name based (v5 style) uuid, as unique as the name it's made from
+fn record_id(name: &str) -> String {
+    let hash = Sha1::digest(name.as_bytes());
+    let mut b = [0u8; 16];
+    b.copy_from_slice(&hash[..16]);
+    b[6] = (b[6] & 0x0f) | 0x50;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex = b.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+    format!("<urn:uuid:{}-{}-{}-{}-{}>", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn info() -> CdxInfo {
+        CdxInfo::parse_row("http://davemolk.com/", "20150925144711", "200", "text/html", "-", "414").unwrap()
+    }
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wayback_warc_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_record_id() {
+        let id = record_id("response http://davemolk.com/ 2015-09-25T14:47:11Z");
+        assert_eq!(id, record_id("response http://davemolk.com/ 2015-09-25T14:47:11Z"));
+        assert_eq!(47, id.len());
+        assert_eq!(Some('5'), id.chars().nth(24));
+    }
+    #[test]
+    fn test_archived_headers() {
+        let headers = [("X-Archive-Orig-Content-Type", "text/html"), ("content-type", "text/html; wayback"), ("x-archive-orig-server", "nginx")];
+        assert_eq!(
+            vec![("content-type".to_owned(), "text/html".to_owned()), ("server".to_owned(), "nginx".to_owned())],
+            archived_headers(headers.into_iter()),
+        );
+    }
+    #[test]
+    fn test_write_warc() {
+        let dir = temp_dir("plain");
+        let mut writer = WarcWriter::new(&dir, false);
+        let headers = vec![("content-type".to_owned(), "text/html".to_owned()), ("transfer-encoding".to_owned(), "chunked".to_owned())];
+        writer.write_capture(&info(), &headers, b"<html>home</html>").unwrap();
+        writer.write_capture(&info(), &[], b"<html>home</html>").unwrap();
+        drop(writer);
+        let warc = std::fs::read_to_string(dir.join("wayback-00000.warc")).unwrap();
+        let records = warc.split("WARC/1.1\r\n").skip(1).collect::<Vec<_>>();
+        assert_eq!(3, records.len());
+        assert!(records[0].starts_with("WARC-Type: warcinfo\r\n"));
+        let response = records[1];
+        assert!(response.starts_with("WARC-Type: response\r\n"));
+        assert!(response.contains("WARC-Date: 2015-09-25T14:47:11Z\r\n"));
+        assert!(response.contains("WARC-Target-URI: http://davemolk.com/\r\n"));
+        assert!(response.contains(&format!("WARC-Payload-Digest: sha1:{}\r\n", digest(b"<html>home</html>"))));
+        let block = "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: 17\r\n\r\n<html>home</html>";
+        assert!(response.ends_with(&format!("Content-Length: {}\r\n\r\n{}\r\n\r\n", block.len(), block)));
+        assert!(records[2].starts_with("WARC-Type: resource\r\n"));
+        assert!(records[2].contains("Content-Type: text/html\r\nContent-Length: 17\r\n\r\n<html>home</html>\r\n\r\n"));
+
+        // a second writer leaves the first file alone
+        let mut writer = WarcWriter::new(&dir, false);
+        writer.write_capture(&info(), &[], b"<html>home</html>").unwrap();
+        assert!(dir.join("wayback-00001.warc").exists());
+        assert_eq!(warc, std::fs::read_to_string(dir.join("wayback-00000.warc")).unwrap());
+        // and every record has its own id, even the same capture written again
+        let again = std::fs::read_to_string(dir.join("wayback-00001.warc")).unwrap();
+        let ids = format!("{}{}", warc, again).lines()
+            .filter_map(|l| l.strip_prefix("WARC-Record-ID: ").map(str::to_owned))
+            .collect::<Vec<_>>();
+        assert_eq!(5, ids.len());
+        assert_eq!(5, ids.iter().collect::<std::collections::HashSet<_>>().len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_write_warc_gzip() {
+        let dir = temp_dir("gzip");
+        let mut writer = WarcWriter::new(&dir, true);
+        writer.write_capture(&info(), &[], b"one").unwrap();
+        writer.write_capture(&info(), &[], b"two").unwrap();
+        drop(writer);
+        let data = std::fs::read(dir.join("wayback-00000.warc.gz")).unwrap();
+        // one gzip member per record
+        assert_eq!(3, data.windows(3).filter(|w| *w == [0x1f, 0x8b, 0x08]).count());
+        let mut warc = String::new();
+        gzip::MultiDecoder::new(&data[..]).unwrap().read_to_string(&mut warc).unwrap();
+        assert_eq!(3, warc.matches("WARC/1.1\r\n").count());
+        assert!(warc.ends_with("\r\n\r\ntwo\r\n\r\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
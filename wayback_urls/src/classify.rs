@@ -0,0 +1,231 @@
+// --classify, urls sorted into the kinds worth a closer look, and which query
+// parameters turn up across them
+use reqwest::Url;
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use wayback_api::timemap::TimemapRow;
+
+const JS_EXTENSIONS: &[&str] = &["js", "mjs", "jsx"];
+const BACKUP_EXTENSIONS: &[&str] = &[
+    "bak", "backup", "old", "orig", "save", "swp", "tmp", "sql", "dump", "db", "sqlite",
+    "zip", "tar", "gz", "tgz", "bz2", "7z", "rar",
+];
+const CONFIG_EXTENSIONS: &[&str] = &["env", "ini", "conf", "config", "cfg", "yml", "yaml", "toml", "properties", "plist"];
+// whole file names, for the ones that are all extension or have none
+const CONFIG_FILES: &[&str] = &[".env", ".htaccess", ".htpasswd", ".npmrc", ".dockerignore", "web.config", "wp-config.php", "dockerfile"];
+// config.json, settings.php and so on, whatever the extension
+const CONFIG_STEMS: &[&str] = &["config", "configuration", "settings", "appsettings", "secrets", "credentials"];
+const API_SEGMENTS: &[&str] = &["api", "apis", "graphql", "rest", "rpc", "jsonrpc", "soap", "services", "swagger", "openapi"];
+const API_EXTENSIONS: &[&str] = &["asmx", "wsdl", "svc"];
+// words in a path segment, between -, _ and . (wp-admin, login.php), so
+// /badminton and /designing-for-rust don't count
+const ADMIN_WORDS: &[&str] = &["admin", "administrator", "login", "logon", "signin", "sign-in", "log-in", "cpanel", "dashboard"];
+const ADMIN_SEGMENTS: &[&str] = &["auth", "oauth", "oauth2", "sso", "manage", "manager", "console"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    Js,
+    Json,
+    Api,
+    Admin,
+    Backup,
+    Config,
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Bucket::Js => "js",
+            Bucket::Json => "json",
+            Bucket::Api => "api",
+            Bucket::Admin => "admin/login",
+            Bucket::Backup => "backups",
+            Bucket::Config => "config",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// every bucket a url goes in, going by its path and the mime type it was archived
+/// with. /api/config.json is api, json and config.
+pub fn classify(url: &str, mime_type: &str) -> Vec<Bucket> {
+    let path = match Url::parse(url) {
+        Ok(parsed) => parsed.path().to_lowercase(),
+        Err(_) => url.split(['?', '#']).next().unwrap_or("").to_lowercase(),
+    };
+    let segments = path.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    let file = segments.last().copied().unwrap_or("");
+    let (stem, ext) = file.rsplit_once('.').unwrap_or((file, ""));
+    let mut out = Vec::new();
+    if JS_EXTENSIONS.contains(&ext) || file.ends_with(".js.map") || mime_type.contains("javascript") {
+        out.push(Bucket::Js);
+    }
+    if ext == "json" || mime_type == "application/json" {
+        out.push(Bucket::Json);
+    }
+    let is_version = |s: &str| s.len() > 1 && s.starts_with('v') && s[1..].chars().all(|c| c.is_ascii_digit());
+    if segments.iter().any(|s| API_SEGMENTS.contains(s) || is_version(s)) || API_EXTENSIONS.contains(&ext) {
+        out.push(Bucket::Api);
+    }
+    if segments.iter().any(|s| ADMIN_SEGMENTS.contains(s) || ADMIN_WORDS.iter().any(|w| has_word(s, w))) {
+        out.push(Bucket::Admin);
+    }
+    if BACKUP_EXTENSIONS.contains(&ext) || file.ends_with('~') {
+        out.push(Bucket::Backup);
+    }
+    if CONFIG_EXTENSIONS.contains(&ext) || CONFIG_FILES.contains(&file) || CONFIG_STEMS.contains(&stem) || segments.contains(&".git") {
+        out.push(Bucket::Config);
+    }
+    out
+}
+
+// word (which may have a - in it) on its own in segment
+fn has_word(segment: &str, word: &str) -> bool {
+    format!("-{}-", segment.replace(['_', '.'], "-")).contains(&format!("-{}-", word))
+}
+
+/// the names of the query parameters in a url, each once.
+pub fn param_names(url: &str) -> Vec<String> {
+    let query = match Url::parse(url) {
+        Ok(parsed) => parsed.query().unwrap_or("").to_owned(),
+        Err(_) => url.split_once('?').map(|(_, q)| q.split('#').next().unwrap_or("").to_owned()).unwrap_or_default(),
+    };
+    let mut seen = HashSet::new();
+    query.split('&')
+        .map(|pair| pair.split('=').next().unwrap_or(""))
+        .filter(|name| !name.is_empty() && seen.insert(*name))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Classified {
+    pub buckets: BTreeMap<Bucket, Vec<String>>,
+    /// parameter names and how many urls have them, most common first.
+    pub params: Vec<Param>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Param {
+    pub name: String,
+    pub count: usize,
+}
+
+impl Classified {
+    pub fn new<'a>(rows: impl IntoIterator<Item = &'a TimemapRow>) -> Self {
+        let mut classified = Classified::default();
+        let mut params: HashMap<String, usize> = HashMap::new();
+        for row in rows {
+            for bucket in classify(&row.original, &row.mime_type) {
+                classified.buckets.entry(bucket).or_default().push(row.original.clone());
+            }
+            for name in param_names(&row.original) {
+                *params.entry(name).or_default() += 1;
+            }
+        }
+        classified.params = params.into_iter().map(|(name, count)| Param { name, count }).collect();
+        classified.params.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(&b.name)));
+        classified
+    }
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (bucket, urls) in &self.buckets {
+            writeln!(f, "{} ({})", bucket, urls.len())?;
+            for url in urls {
+                writeln!(f, "  {}", url)?;
+            }
+        }
+        if self.params.is_empty() {
+            return Ok(());
+        }
+        let width = self.params.iter().map(|p| p.name.chars().count()).max().unwrap_or(0).max("parameter".len());
+        writeln!(f, "{:<width$}  urls", "parameter")?;
+        for param in &self.params {
+            writeln!(f, "{:<width$}  {}", param.name, param.count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(original: &str, mime_type: &str) -> TimemapRow {
+        TimemapRow {
+            original: original.to_owned(),
+            mime_type: mime_type.to_owned(),
+            from: None,
+            to: None,
+            group_count: 1,
+            uniq_count: 1,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(vec![Bucket::Js], classify("http://rust-lang.org/static/app.min.js?v=3", "text/plain"));
+        assert_eq!(vec![Bucket::Js], classify("http://rust-lang.org/static/app.js.map", "unk"));
+        assert_eq!(vec![Bucket::Js], classify("http://rust-lang.org/bundle", "application/javascript"));
+        assert_eq!(vec![Bucket::Json, Bucket::Api, Bucket::Config], classify("https://rust-lang.org/api/config.json", "unk"));
+        assert_eq!(vec![Bucket::Api], classify("https://rust-lang.org/v2/users/1", "text/html"));
+        assert_eq!(vec![Bucket::Api], classify("https://rust-lang.org/Service.asmx", "text/html"));
+        assert_eq!(vec![Bucket::Admin], classify("https://rust-lang.org/wp-admin/", "text/html"));
+        assert_eq!(vec![Bucket::Admin], classify("https://rust-lang.org/users/Login.aspx", "text/html"));
+        assert_eq!(vec![Bucket::Admin], classify("https://rust-lang.org/oauth/authorize", "text/html"));
+        assert_eq!(vec![Bucket::Admin], classify("https://rust-lang.org/account/sign-in", "text/html"));
+        assert_eq!(vec![Bucket::Admin], classify("https://rust-lang.org/user_login/", "text/html"));
+        // words, not bits of words
+        assert!(classify("https://rust-lang.org/designing-for-rust", "text/html").is_empty());
+        assert!(classify("https://rust-lang.org/badminton", "text/html").is_empty());
+        assert!(classify("https://rust-lang.org/blog/catalogin", "text/html").is_empty());
+        assert!(classify("https://rust-lang.org/dashboards-are-fun/adminx", "text/html").is_empty());
+        assert_eq!(vec![Bucket::Backup], classify("https://rust-lang.org/db/dump.sql", "unk"));
+        assert_eq!(vec![Bucket::Backup], classify("https://rust-lang.org/index.php~", "unk"));
+        assert_eq!(vec![Bucket::Backup], classify("https://rust-lang.org/site.tar.gz", "unk"));
+        assert_eq!(vec![Bucket::Config], classify("https://rust-lang.org/.env", "unk"));
+        assert_eq!(vec![Bucket::Config], classify("https://rust-lang.org/.git/config", "unk"));
+        assert_eq!(vec![Bucket::Config], classify("https://rust-lang.org/web.config", "unk"));
+        // none of which means anything in the query
+        assert!(classify("https://rust-lang.org/?next=/admin&file=a.zip", "text/html").is_empty());
+        assert!(classify("https://rust-lang.org/learn/get-started", "text/html").is_empty());
+        assert!(classify("https://rust-lang.org/versions/", "text/html").is_empty());
+    }
+    #[test]
+    fn test_param_names() {
+        assert_eq!(vec!["id", "q", "debug"], param_names("http://rust-lang.org/a?id=1&q=x&id=2&debug#frag"));
+        assert!(param_names("http://rust-lang.org/a").is_empty());
+        assert_eq!(vec!["page"], param_names("rust-lang.org/a?page=2"));
+    }
+    #[test]
+    fn test_classified() {
+        let rows = [
+            row("http://rust-lang.org/api/users?id=1&token=x", "application/json"),
+            row("http://rust-lang.org/admin/login.php?next=/&id=2", "text/html"),
+            row("http://rust-lang.org/search?q=rust&id=3", "text/html"),
+            row("http://rust-lang.org/backup.zip", "application/zip"),
+        ];
+        let classified = Classified::new(&rows);
+        assert_eq!(vec![Bucket::Json, Bucket::Api, Bucket::Admin, Bucket::Backup], classified.buckets.keys().copied().collect::<Vec<_>>());
+        assert_eq!(Param { name: "id".to_owned(), count: 3 }, classified.params[0]);
+        assert_eq!(vec!["id", "next", "q", "token"], classified.params.iter().map(|p| p.name.as_str()).collect::<Vec<_>>());
+        let out = classified.to_string();
+        assert!(out.starts_with("json (1)\n  http://rust-lang.org/api/users?id=1&token=x\napi (1)\n"));
+        assert!(out.ends_with("parameter  urls\nid         3\nnext       1\nq          1\ntoken      1\n"));
+        let json = serde_json::to_value(&classified).unwrap();
+        assert_eq!("http://rust-lang.org/backup.zip", json["buckets"]["backup"][0]);
+        assert_eq!(3, json["params"][0]["count"]);
+    }
+    #[test]
+    fn test_classify_fixture() {
+        let page = wayback_api::table::Page::parse(&std::fs::read_to_string("wayback_response.json").unwrap()).unwrap();
+        let classified = Classified::new(&page.parse_rows::<TimemapRow>().unwrap());
+        // rust-lang.org's archived junk is nothing interesting
+        assert!(classified.buckets.is_empty());
+        assert!(classified.params.is_empty());
+    }
+}
@@ -1,191 +1,488 @@
 use clap::Parser;
-use reqwest;
 use anyhow::{Result, anyhow};
-use std::time::{SystemTime, UNIX_EPOCH};
-use chrono::NaiveDateTime;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use wayback_api::date::{parse_period, Period};
+use wayback_api::paging::{Limit, Pages};
+use wayback_api::table::{FromRow, Page};
+use wayback_api::timemap::TimemapRow;
+use wayback_api::transport::{HttpTransport, Throttled, Transport};
+
+use crate::classify::Classified;
+use crate::filter::{DateFilter, Seen};
+use crate::output::{dedup_rows, sort_rows, write_rows, Field, Format, Found, Sort};
+use crate::probe::Prober;
+use crate::query::{parse_mime, parse_status, MatchType, Query, DEFAULT_COLLAPSE};
 
 #[derive(Parser)]
 pub struct Args {
-    #[clap(short, long)]
-    domain: String,
+    #[clap(short, long, required_unless_present = "input", conflicts_with = "input")]
+    domain: Option<String>,
+    /// file of domains to look up, one per line, or - for stdin. results say
+    /// which domain they're for.
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// what counts as a match for the domain.
+    #[arg(long, value_enum, default_value_t)]
+    match_type: MatchType,
+    /// subdomains too, same as --match-type domain.
+    #[arg(long, action, conflicts_with = "match_type")]
+    subdomains: bool,
+    /// only captures with these statuses, like 200 or 3xx, comma separated.
+    #[arg(long, value_delimiter = ',', value_parser = parse_status)]
+    status: Vec<String>,
+    /// no captures with these statuses. 4xx and 5xx unless there's a --status,
+    /// and nothing when given on its own.
+    #[arg(long, value_delimiter = ',', num_args = 0.., value_parser = parse_status)]
+    exclude_status: Option<Vec<String>>,
+    /// only captures with these mime types, * for anything, comma separated.
+    #[arg(long, value_delimiter = ',', value_parser = parse_mime)]
+    mime: Vec<String>,
+    /// no captures with these mime types.
+    #[arg(long, value_delimiter = ',', value_parser = parse_mime)]
+    exclude_mime: Vec<String>,
+    /// fields captures are collapsed on, like urlkey or timestamp:6, and
+    /// nothing when given on its own.
+    #[arg(long, value_delimiter = ',', num_args = 0.., default_value = DEFAULT_COLLAPSE)]
+    collapse: Vec<String>,
+    /// how many domains to look up at once.
+    #[arg(short, long, default_value_t = 4)]
+    concurrency: usize,
+    /// requests per second, across every domain, 0 for no limit.
+    #[arg(long, default_value_t = 1.0)]
+    rate: f64,
     #[clap(short, long, action)]
     verbose: bool,
-    #[clap(short, long)]
-    #[arg(value_parser = parse_ndt)]
-    before: Option<NaiveDateTime>,
-    #[clap(short, long)]
-    #[arg(value_parser = parse_ndt)]
-    after: Option<NaiveDateTime>,
-}
-
-fn parse_ndt(arg: &str) -> Result<NaiveDateTime> {
-    let date_v = validate_ndt(arg)?;
-    let with_time_added = format!("{}000000", date_v);
-    let ndt = NaiveDateTime::parse_from_str(&with_time_added, "%Y%m%d%H%M%S")?;
-    Ok(ndt)
-}
-
-fn validate_ndt(arg: &str) -> Result<String> {
-    let date_v: Vec<&str> = arg.split("-").collect();
-    // not comprehensive checking, but fine for this project
-    anyhow::ensure!(date_v.len() == 3, "need date as yyyy-dd-mm");
-    anyhow::ensure!(date_v[0].len() == 4, "need a valid year");
-    anyhow::ensure!(date_v[1].len() > 0 && date_v[1].len() < 3, "need a valid day");
-    let day = if date_v[1].len() == 2 { date_v[1].to_string() } else { format!("0{}", date_v[1]) };
-    anyhow::ensure!(date_v[2].len() > 0 && date_v[2].len() < 3, "need a valid month");
-    let month = if date_v[2].len() == 2 { date_v[2].to_string() } else { format!("0{}", date_v[2]) };
-    Ok(format!("{}{}{}", date_v[0].to_string(), day, month))
-}
-
-fn parse_result_to_ndt(date: &str) -> Result<NaiveDateTime> {
-    let ndt = NaiveDateTime::parse_from_str(&date, "%Y%m%d%H%M%S")?;
-    Ok(ndt)
+    /// only urls captured on or after this date, YYYY-MM-DD, YYYY-MM or YYYY.
+    #[arg(short, long, short_alias = 'a', alias = "after", value_parser = parse_period)]
+    since: Option<Period>,
+    /// only urls captured on or before this date, all of it for a month or year.
+    #[arg(short, long, short_alias = 'b', alias = "before", value_parser = parse_period)]
+    until: Option<Period>,
+    /// which captures --since and --until go by.
+    #[arg(long, value_enum, default_value_t)]
+    seen: Seen,
+    /// how to print results.
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+    /// fields to print, comma separated. plain only goes by whether domain is
+    /// there, which it always is with --input.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "original,mimetype,from,to,groupcount,uniqcount")]
+    fields: Vec<Field>,
+    /// order of the results, as the timemap has them otherwise.
+    #[arg(long, value_enum)]
+    sort: Option<Sort>,
+    /// one result per url, ignoring scheme, trailing slashes, query order and so on.
+    #[arg(long, action)]
+    dedup: bool,
+    /// sort urls into js, json, api, admin/login, backups and config, and count
+    /// query parameter names, instead of listing them. plain or json format.
+    #[arg(long, action)]
+    classify: bool,
+    /// rows to ask for per request, following resume keys for the rest.
+    #[arg(long, default_value_t = DEFAULT_LIMIT)]
+    limit: usize,
+    /// stop after this many rows from the archive, per domain.
+    #[arg(long)]
+    max: Option<usize>,
+    /// see whether each url is still there, with its status now, where it
+    /// redirects to and its length.
+    #[arg(long, action)]
+    probe: bool,
+    /// only urls that answer without an error now.
+    #[arg(long, action, requires = "probe")]
+    only_alive: bool,
+    /// how many urls to probe at once.
+    #[arg(long, default_value_t = 10)]
+    probe_concurrency: usize,
+    /// seconds to give each probe.
+    #[arg(long, default_value_t = 10)]
+    probe_timeout: u64,
 }
 
 pub struct WaybackClient {
     args: Args,
-    client: reqwest::blocking::Client,
+    query: Query,
+    filter: DateFilter,
+    transport: Arc<dyn Transport>,
 }
 
-// hit one of the backend apis to get URLs captured for the given URL prefix
-const WAYBACK_BASE: &str = "https://web.archive.org/web/timemap/json?matchType=prefix&collapse=urlkey&output=json&fl=original%2Cmimetype%2Ctimestamp%2Cendtimestamp%2Cgroupcount%2Cuniqcount&filter=!statuscode%3A[45]..&limit=10000&_=";
+// the channel between the domains being looked up and the output
+const BACKLOG: usize = 1000;
+// what used to be the one and only request
+const DEFAULT_LIMIT: usize = 10000;
 
 impl WaybackClient {
     pub fn new(args: Args) -> Self {
+        let transport = Arc::new(Throttled::new(Arc::new(HttpTransport::new()), args.rate));
+        WaybackClient::with_transport(args, transport)
+    }
+    /// answers requests some other way than over http, mostly for tests.
+    pub fn with_transport(args: Args, transport: Arc<dyn Transport>) -> Self {
         WaybackClient{
+            query: Query {
+                match_type: if args.subdomains { MatchType::Domain } else { args.match_type },
+                status: args.status.clone(),
+                exclude_status: args.exclude_status.clone(),
+                mime: args.mime.clone(),
+                exclude_mime: args.exclude_mime.clone(),
+                collapse: args.collapse.clone(),
+            },
+            filter: DateFilter::new(args.since, args.until, args.seen),
             args,
-            client: reqwest::blocking::Client::new(),
+            transport,
         }
     }
-    fn get_url(&self) -> String {
+    // hit one of the backend apis to get URLs captured for the given domain
+    fn get_url(&self, domain: &str) -> Result<String> {
         let start = SystemTime::now();
         let since_epoch = start.duration_since(UNIX_EPOCH).expect("problem with epoch").as_millis();
-        format!("{}{}&url={}", WAYBACK_BASE, since_epoch, self.args.domain)
+        self.query.url(domain, since_epoch)
     }
-    pub fn run(&self) -> Result<()> {
-        let url = self.get_url();
-        let resp = self.get_all_sitemap(url)?;
-        if resp.len() < 2 {
-            // first vec is always the key, [ "original", "mimetype", "timestamp", "endtimestamp", "groupcount", "uniqcount" ]
-            return Err(anyhow!("failed to get any results"));
+    /// --domain, or every domain in --input once, skipping blank lines and # comments.
+    fn domains(&self) -> Result<Vec<String>> {
+        let Some(input) = &self.args.input else {
+            return Ok(self.args.domain.iter().cloned().collect());
+        };
+        let reader: Box<dyn BufRead> = if input.as_os_str() == "-" {
+            Box::new(std::io::stdin().lock())
+        } else {
+            let file = std::fs::File::open(input).map_err(|e| anyhow!("failed to open {}: {}", input.display(), e))?;
+            Box::new(std::io::BufReader::new(file))
+        };
+        let mut domains: Vec<String> = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let domain = line.trim();
+            if !domain.is_empty() && !domain.starts_with('#') && !domains.iter().any(|d| d == domain) {
+                domains.push(domain.to_owned());
+            }
+        }
+        anyhow::ensure!(!domains.is_empty(), "no domains in {}", input.display());
+        Ok(domains)
+    }
+    // flags that don't go together, before anything gets fetched
+    fn check_args(&self) -> Result<()> {
+        if self.args.classify && !matches!(self.args.format, Format::Plain | Format::Json) {
+            return Err(anyhow!("--classify prints plain or json"));
         }
-        self.print_results(resp);
         Ok(())
     }
-    fn get_all_sitemap(&self, url: String) -> Result<WaybackResponse> {
-        let resp = self.client.get(url)
-            .send()?
-            .json::<WaybackResponse>()?;
-        Ok(resp)
-    }
-    fn print_results(&self, resp: Vec<Vec<String>>) {
-        let filter_before = if self.args.before.is_some() { true } else { false };
-        let filter_after = if self.args.after.is_some() { true } else { false };
-        if self.args.verbose {
-            println!(
-                "{0: <20} | {1: <20} | {2: <11} | {3: <8} | {4: <25} | {5: }",
-                "From:", "To:", "Duplicates:", "Uniques:", "MIME Type:", "URL:"
-            );
-        }
-        for (i, entry) in resp.iter().enumerate() {
-            // skip the key
-            if i == 0 {
-                continue
-            }
-            if entry.len() != 6 {
-                // best effort to print url
-                if entry.len() > 1 {
-                    println!("{}", entry[0]);
-                } 
-                continue
+    pub fn run(&self) -> Result<()> {
+        self.check_args()?;
+        let domains = self.domains()?;
+        let report = self.write_results(&domains, &mut std::io::stdout().lock())?;
+        let tag = |domain: &str| if self.args.input.is_some() { format!("{}: ", domain) } else { String::new() };
+        for (domain, limit) in &report.limits {
+            match limit {
+                Limit::Max(max) => eprintln!("warning: {}stopped at --max {}, there are more results", tag(domain), max),
+                Limit::Page(limit) => eprintln!(
+                    "warning: {}got {} rows and no resume key, results are probably cut short (try a bigger --limit)",
+                    tag(domain), limit,
+                ),
             }
-            // get result dates and parse
-            let mut from = String::new();
-            if let Ok(f) = parse_result_to_ndt(&entry[2]) {
-                from = f.to_string();
-            };
-            let mut to = String::new();
-            if let Ok(t) = parse_result_to_ndt(&entry[3]) {
-                to = t.to_string();
-            };
-            // if we can't get the dates, we can't filter, so just print the url
-            if from.len() == 0 || to.len() == 0 {
-                println!("{}", entry[0]);
-                continue
+        }
+        let mut failed = report.failed;
+        if self.args.input.is_none() {
+            return failed.pop().map_or(Ok(()), |(_, e)| Err(e));
+        }
+        for (domain, e) in &failed {
+            eprintln!("error: {}: {}", domain, e);
+        }
+        anyhow::ensure!(failed.is_empty(), "{} of {} domains failed", failed.len(), domains.len());
+        Ok(())
+    }
+    /// looks the domains up a few at a time, writing results as they come in,
+    /// unless they all have to be there to sort, dedup, probe or classify them.
+    fn write_results(&self, domains: &[String], out: &mut dyn Write) -> Result<Report> {
+        let report = Mutex::new(Report::default());
+        let next = AtomicUsize::new(0);
+        let (tx, rx) = mpsc::sync_channel(BACKLOG);
+        thread::scope(|s| {
+            for _ in 0..self.args.concurrency.clamp(1, domains.len().max(1)) {
+                let tx = tx.clone();
+                let (report, next) = (&report, &next);
+                s.spawn(move || {
+                    while let Some(domain) = domains.get(next.fetch_add(1, Ordering::SeqCst)) {
+                        match self.fetch_domain(domain, &tx) {
+                            Ok(Some(limit)) => report.lock().unwrap().limits.push((domain.clone(), limit)),
+                            Ok(None) => {},
+                            Err(e) => report.lock().unwrap().failed.push((domain.clone(), e)),
+                        }
+                    }
+                });
             }
-            // not filtering
-            if !filter_before && !filter_after {
-                if self.args.verbose {
-                    self.print_line(from, to, &entry);
-                } else {
-                    println!("{}", entry[0]);
-                }
-            } else {
-                // check filter cases
-                if filter_before && filter_after && self.is_before(&entry[2]) && self.is_after(&entry[3]) {
-                    self.print_line(from, to, &entry);
-                } else if filter_before && self.is_before(&entry[2]) {
-                    self.print_line(from, to, &entry);
-                } else if filter_after && self.is_after(&entry[3]) {
-                    self.print_line(from, to, &entry);
-                }
+            // the output is done once every worker's sender is gone
+            drop(tx);
+            let result = self.print_results(out, rx.iter());
+            // and if it gives up first, the workers' sends fail instead of waiting
+            drop(rx);
+            result
+        })?;
+        Ok(report.into_inner().unwrap())
+    }
+    // every page for a domain, sent on as it comes in. says if there are more
+    // than it got to.
+    fn fetch_domain(&self, domain: &str, tx: &mpsc::SyncSender<Found>) -> Result<Option<Limit>> {
+        let mut pages = Pages::new(self.transport.as_ref(), &self.get_url(domain)?, self.args.limit, self.args.max);
+        let mut found = 0;
+        for page in pages.by_ref() {
+            let page = page?;
+            found += page.rows.len();
+            for row in self.parse_rows(&page) {
+                // only fails if the output gave up
+                tx.send(Found::new(domain, row)).map_err(|_| anyhow!("output closed"))?;
             }
         }
-    }
-    fn print_line(&self, from: String, to: String, line: &Vec<String>) {
-        if self.args.verbose {
-            println!("{0: <20} | {1: <20} | {2: <11} | {3: <8} | {4: <25} | {5: }", from, to, line[4], line[5], line[1], line[0]);
-        } else {
-            println!("{}", line[0]);
+        if found == 0 {
+            return Err(anyhow!("failed to get any results"));
         }
+        Ok(pages.limit_hit())
     }
-    fn is_before(&self, date: &str) -> bool {
-        self.args.before.unwrap() - parse_result_to_ndt(date).unwrap() > chrono::TimeDelta::new(0, 0).unwrap()
+    // the rows of a page in the date range
+    fn parse_rows(&self, page: &Page) -> Vec<TimemapRow> {
+        page.rows.iter()
+            .filter_map(|entry| match TimemapRow::from_row(&page.fields, entry) {
+                Ok(row) => Some(row),
+                // best effort to print url
+                Err(_) if entry.len() > 1 => Some(TimemapRow {
+                    original: entry[0].clone(),
+                    mime_type: "-".to_owned(),
+                    from: None,
+                    to: None,
+                    group_count: 0,
+                    uniq_count: 0,
+                }),
+                Err(_) => None,
+            })
+            .filter(|row| self.filter.matches(row))
+            .collect()
     }
-    fn is_after(&self, date: &str) -> bool {
-        parse_result_to_ndt(date).unwrap() - self.args.after.unwrap() > chrono::TimeDelta::new(0, 0).unwrap()
+    fn print_results(&self, out: &mut dyn Write, rows: impl Iterator<Item = Found>) -> Result<()> {
+        let mut fields = self.args.fields.clone();
+        if self.args.input.is_some() && !fields.contains(&Field::Domain) {
+            fields.insert(0, Field::Domain);
+        }
+        if self.args.probe {
+            for field in [Field::Status, Field::Target, Field::Length] {
+                if !fields.contains(&field) {
+                    fields.push(field);
+                }
+            }
+        }
+        if !self.args.dedup && self.args.sort.is_none() && !self.args.classify && !self.args.probe {
+            return write_rows(out, self.args.format, &fields, self.args.verbose, rows);
+        }
+        let mut rows = rows.collect::<Vec<_>>();
+        if self.args.dedup {
+            rows = dedup_rows(rows);
+        }
+        if self.args.probe {
+            let prober = Prober::new(Duration::from_secs(self.args.probe_timeout), self.args.probe_concurrency)?;
+            rows = prober.probe_all(rows);
+            if self.args.only_alive {
+                rows.retain(|found| found.probe.as_ref().is_some_and(|p| p.is_alive()));
+            }
+        }
+        if let Some(sort) = self.args.sort {
+            sort_rows(&mut rows, sort);
+        }
+        if self.args.classify {
+            let classified = Classified::new(rows.iter().map(|found| &found.row));
+            // check_args keeps out the other formats
+            match self.args.format {
+                Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(&classified)?)?,
+                _ => write!(out, "{}", classified)?,
+            }
+            return Ok(());
+        }
+        write_rows(out, self.args.format, &fields, self.args.verbose, rows.into_iter())
     }
 }
 
-type WaybackResponse = Vec<Vec<String>>;
+/// the domains that didn't go as planned.
+#[derive(Default)]
+struct Report {
+    limits: Vec<(String, Limit)>,
+    failed: Vec<(String, anyhow::Error)>,
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use chrono::NaiveDate;
-    trait WaybackAPI {
-        fn get_all_sitemap(&self, url: String) -> Result<WaybackResponse>;
-    }
-    struct ClientMock{}
-        impl WaybackAPI for ClientMock {
-            fn get_all_sitemap(&self, _url: String) -> Result<WaybackResponse> {
-                let data = std::fs::read_to_string("wayback_response.json").expect("failed to read test data");
-                let resp: WaybackResponse = serde_json::from_str(&data)?;
-                Ok(resp)
+    use wayback_api::test_server::{Response, TestServer};
+    use wayback_api::transport::Replay;
+
+    fn client(args: &[&str], replay: &Arc<Replay>) -> WaybackClient {
+        let args = Args::parse_from(["wayback_urls", "--domain", "rust-lang.org"].iter().chain(args));
+        WaybackClient::with_transport(args, replay.clone())
+    }
+    fn write_results(client: &WaybackClient) -> Result<(String, Option<Limit>)> {
+        let mut out = Vec::new();
+        let mut report = client.write_results(&client.domains()?, &mut out)?;
+        if let Some((_, e)) = report.failed.pop() {
+            return Err(e);
+        }
+        Ok((String::from_utf8(out).unwrap(), report.limits.pop().map(|(_, limit)| limit)))
+    }
+
+    #[test]
+    fn test_write_results() {
+        let replay = Arc::new(Replay::new().fixture_file("url=rust-lang.org", "wayback_response.json").unwrap());
+        let (out, hit) = write_results(&client(&[], &replay)).unwrap();
+        assert_eq!(9, out.lines().count());
+        assert!(out.starts_with("http://rust-lang.org/\n"));
+        assert_eq!(None, hit);
+        let requests = replay.requests();
+        assert_eq!(1, requests.len());
+        assert!(requests[0].ends_with("&url=rust-lang.org&limit=10000&showResumeKey=true"));
+        // everything there is fits, but a page this full with no key looks cut short
+        let (_, hit) = write_results(&client(&["--limit", "9"], &replay)).unwrap();
+        assert_eq!(Some(Limit::Page(9)), hit);
+    }
+    #[test]
+    fn test_write_results_pages() {
+        let fields = r#"["original","mimetype","timestamp","endtimestamp","groupcount","uniqcount"]"#;
+        let row = |n: u32| format!(r#"["http://rust-lang.org/{}","text/html","2015{:02}01000000","20200101000000","1","1"]"#, n, n);
+        let replay = Arc::new(Replay::new()
+            .fixture("resumeKey=two", &format!("[{},{},{}]", fields, row(3), row(4)))
+            .fixture("", &format!("[{},{},{},[],[\"two\"]]", fields, row(1), row(2))));
+        let (out, hit) = write_results(&client(&["--limit", "2"], &replay)).unwrap();
+        assert_eq!("http://rust-lang.org/1\nhttp://rust-lang.org/2\nhttp://rust-lang.org/3\nhttp://rust-lang.org/4\n", out);
+        assert_eq!(None, hit);
+        let (out, hit) = write_results(&client(&["--limit", "2", "--max", "3", "--sort", "url"], &replay)).unwrap();
+        assert_eq!(3, out.lines().count());
+        assert_eq!(Some(Limit::Max(3)), hit);
+        // the date range applies across pages
+        let (out, _) = write_results(&client(&["--limit", "2", "--since", "2015-03", "--seen", "first"], &replay)).unwrap();
+        assert_eq!("http://rust-lang.org/3\nhttp://rust-lang.org/4\n", out);
+    }
+    #[test]
+    fn test_write_results_empty() {
+        let replay = Arc::new(Replay::new().fixture("", ""));
+        assert!(write_results(&client(&[], &replay)).unwrap_err().to_string().contains("failed to get any results"));
+        let replay = Arc::new(Replay::new());
+        assert!(write_results(&client(&[], &replay)).is_err());
+    }
+    #[test]
+    fn test_write_results_output_fails() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
             }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let fields = r#"["original","mimetype","timestamp","endtimestamp","groupcount","uniqcount"]"#;
+        let rows = (0..BACKLOG + 100)
+            .map(|n| format!(r#"["http://rust-lang.org/{}","text/html","20150101000000","20200101000000","1","1"]"#, n))
+            .collect::<Vec<_>>();
+        let replay = Arc::new(Replay::new().fixture("", &format!("[{},{}]", fields, rows.join(","))));
+        let client = client(&[], &replay);
+        // more rows than the channel holds, and nothing taking them
+        let Err(err) = client.write_results(&client.domains().unwrap(), &mut Closed) else {
+            panic!("wrote to a closed output");
+        };
+        assert_eq!(std::io::ErrorKind::BrokenPipe, err.downcast::<std::io::Error>().unwrap().kind());
+    }
+    #[test]
+    fn test_classify_format() {
+        let replay = Arc::new(Replay::new().fixture_file("url=rust-lang.org", "wayback_response.json").unwrap());
+        for format in ["csv", "tsv", "jsonl"] {
+            let err = client(&["--classify", "--format", format], &replay).run().unwrap_err();
+            assert_eq!("--classify prints plain or json", err.to_string());
         }
+        // turned down before asking the archive for anything
+        assert!(replay.requests().is_empty());
+        let (out, _) = write_results(&client(&["--classify", "--format", "json"], &replay)).unwrap();
+        assert!(out.starts_with('{'));
+        assert!(client(&["--format", "csv"], &replay).check_args().is_ok());
+    }
+    #[test]
+    fn test_date_args() {
+        let args = Args::parse_from(["wayback_urls", "-d", "rust-lang.org", "--since", "2020-10-31", "-b", "2021"]);
+        let expect = NaiveDate::from_ymd_opt(2020, 10, 31).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(Some(expect), args.since.map(|p| p.start));
+        assert_eq!(Some(NaiveDate::from_ymd_opt(2022, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()), args.until.map(|p| p.end));
+        assert_eq!(Seen::Any, args.seen);
+        assert!(Args::try_parse_from(["wayback_urls", "-d", "rust-lang.org", "-a", "10/31/200"]).is_err());
+        assert!(Args::try_parse_from(["wayback_urls", "-d", "rust-lang.org", "--since", "2020-31-10"]).is_err());
+    }
     #[test]
-    fn test_get_all_sitemap() {
-        let client = ClientMock{};
-        let resp = client.get_all_sitemap("blah".to_string()).expect("get comments failed");
-        assert_eq!(10, resp.len());
-        assert_eq!(String::from("http://rust-lang.org/"), resp[1][0]);
+    fn test_subdomains() {
+        let replay = Arc::new(Replay::new().fixture_file("url=rust-lang.org", "wayback_response.json").unwrap());
+        write_results(&client(&[], &replay)).unwrap();
+        write_results(&client(&["--subdomains"], &replay)).unwrap();
+        let requests = replay.requests();
+        assert!(requests[0].contains("?matchType=prefix&"));
+        assert!(requests[1].contains("?matchType=domain&"));
+        assert!(Args::try_parse_from(["wayback_urls", "-d", "rust-lang.org", "--subdomains", "--match-type", "host"]).is_err());
     }
     #[test]
-    fn test_parse_ndt() {
-        let ndt = parse_ndt("2020-10-31").expect("want valid date");
-        let expect_ndt = NaiveDate::from_ymd_opt(2020, 10, 31).unwrap().and_hms_opt(0, 0, 0).unwrap();
-        assert_eq!(ndt, expect_ndt);
+    fn test_input() {
+        let dir = std::env::temp_dir().join(format!("wayback_urls_input_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("domains.txt");
+        std::fs::write(&input, "# some domains\nrust-lang.org\n\n  davemolk.com  \nnope.com\nrust-lang.org\n").unwrap();
+        let fields = r#"["original","mimetype","timestamp","endtimestamp","groupcount","uniqcount"]"#;
+        let replay = Arc::new(Replay::new()
+            .fixture_file("url=rust-lang.org", "wayback_response.json").unwrap()
+            .fixture("url=davemolk.com", &format!(r#"[{},["http://davemolk.com/","text/html","20150925144711","20200101000000","1","1"]]"#, fields)));
+        let args = Args::parse_from(["wayback_urls", "--input", input.to_str().unwrap(), "-c", "2"]);
+        assert!(Args::try_parse_from(["wayback_urls", "-d", "rust-lang.org", "-i", input.to_str().unwrap()]).is_err());
+        assert!(Args::try_parse_from(["wayback_urls"]).is_err());
+        let client = WaybackClient::with_transport(args, replay.clone());
+        assert_eq!(vec!["rust-lang.org", "davemolk.com", "nope.com"], client.domains().unwrap());
+        let mut out = Vec::new();
+        let report = client.write_results(&client.domains().unwrap(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        // one domain failing doesn't stop the others, and rows say where they're from
+        assert_eq!(10, out.lines().count());
+        assert!(out.contains("davemolk.com\thttp://davemolk.com/\n"));
+        assert!(out.contains("rust-lang.org\thttp://rust-lang.org/\n"));
+        assert_eq!(1, report.failed.len());
+        assert_eq!("nope.com", report.failed[0].0);
+        assert_eq!(3, replay.requests().len());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
     #[test]
-    #[should_panic]
-    fn test_parse_ndt_fail() {
-        _ = parse_ndt("10/31/200").expect("want valid date");
+    fn test_query_args() {
+        let query = |args: &[&str]| client(args, &Arc::new(Replay::new())).query;
+        assert_eq!(Query { collapse: vec!["urlkey".to_owned()], ..Default::default() }, query(&[]));
+        let q = query(&["--status", "200,3xx", "--exclude-mime", "image/*", "--collapse", "--match-type", "host"]);
+        assert_eq!(vec!["200", "3.."], q.status);
+        assert_eq!(vec!["image/.*"], q.exclude_mime);
+        assert!(q.collapse.is_empty());
+        assert_eq!(MatchType::Host, q.match_type);
+        assert_eq!(Some(Vec::new()), query(&["--exclude-status"]).exclude_status);
+        assert_eq!(Some(vec!["404".to_owned()]), query(&["--exclude-status", "404"]).exclude_status);
+        assert_eq!(vec!["digest", "timestamp:8"], query(&["--collapse", "digest,timestamp:8"]).collapse);
+        assert!(Args::try_parse_from(["wayback_urls", "-d", "rust-lang.org", "--status", "ok"]).is_err());
     }
     #[test]
-    fn test_parse_result_to_ndt() {
-        let got = parse_result_to_ndt("20240228105514").expect("want valid");
-        let expect = NaiveDate::from_ymd_opt(2024, 02, 28).unwrap().and_hms_opt(10, 55, 14).unwrap();
-        assert_eq!(got, expect);
+    fn test_probe() {
+        let server = TestServer::start(|path| match path {
+            "/" => Response::ok(b"home"),
+            "/old" => Response::status(302).header("Location", "/"),
+            _ => Response::status(404),
+        });
+        let fields = r#"["original","mimetype","timestamp","endtimestamp","groupcount","uniqcount"]"#;
+        let row = |path: &str| format!(r#"["{}{}","text/html","20150101000000","20200101000000","1","1"]"#, server.base, path);
+        let replay = Arc::new(Replay::new().fixture("", &format!("[{},{},{},{}]", fields, row("/"), row("/gone"), row("/old"))));
+        let (out, _) = write_results(&client(&["--probe"], &replay)).unwrap();
+        assert_eq!(
+            format!("200\t{0}/\n404\t{0}/gone\n200\t{0}/old -> {0}/\n", server.base),
+            out,
+        );
+        let (out, _) = write_results(&client(&["--probe", "--only-alive", "--format", "csv", "--fields", "original"], &replay)).unwrap();
+        assert_eq!(format!("original,status,target,length\n{0}/,200,{0}/,4\n{0}/old,200,{0}/,4\n", server.base), out);
+        assert!(Args::try_parse_from(["wayback_urls", "-d", "rust-lang.org", "--only-alive"]).is_err());
     }
 }
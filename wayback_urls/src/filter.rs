@@ -0,0 +1,108 @@
+// which timemap rows fall in the range asked for with --since and --until
+use chrono::NaiveDateTime;
+use wayback_api::date::Period;
+use wayback_api::timemap::TimemapRow;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Seen {
+    /// when the url was first captured.
+    First,
+    /// when it was last captured.
+    Last,
+    /// captured at some point in the range, going by its first and last capture.
+    #[default]
+    Any,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DateFilter {
+    since: Option<NaiveDateTime>,
+    // exclusive, so --until 2020 takes in all of 2020
+    until: Option<NaiveDateTime>,
+    seen: Seen,
+}
+
+impl DateFilter {
+    pub fn new(since: Option<Period>, until: Option<Period>, seen: Seen) -> Self {
+        DateFilter { since: since.map(|p| p.start), until: until.map(|p| p.end), seen }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+    /// once there's a range, rows without the dates to tell are left out.
+    pub fn matches(&self, row: &TimemapRow) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let (Some(first), Some(last)) = (row.from, row.to) else {
+            return false;
+        };
+        let (start, end) = match self.seen {
+            Seen::First => (first, first),
+            Seen::Last => (last, last),
+            Seen::Any => (first, last),
+        };
+        self.since.is_none_or(|since| end >= since) && self.until.is_none_or(|until| start < until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayback_api::date::parse_period;
+    use wayback_api::table::Page;
+
+    fn matching(since: Option<&str>, until: Option<&str>, seen: Seen) -> Vec<String> {
+        let page = Page::parse(&std::fs::read_to_string("wayback_response.json").unwrap()).unwrap();
+        let filter = DateFilter::new(since.map(|s| parse_period(s).unwrap()), until.map(|u| parse_period(u).unwrap()), seen);
+        page.parse_rows::<TimemapRow>().unwrap().into_iter().filter(|r| filter.matches(r)).map(|r| r.original).collect()
+    }
+
+    #[test]
+    fn test_no_filter() {
+        assert_eq!(9, matching(None, None, Seen::Any).len());
+    }
+    #[test]
+    fn test_since_until_first_seen() {
+        assert_eq!(
+            vec!["https://rust-lang.org/%22,", "https://rust-lang.org/%0A", "http://www.rust-lang.org/%E2%80%A2"],
+            matching(Some("2024"), None, Seen::First),
+        );
+        // the whole of the last day counts
+        assert_eq!(
+            vec!["http://rust-lang.org/", "http://www.rust-lang.org/%22%3ERust"],
+            matching(None, Some("2018-04-14"), Seen::First),
+        );
+        assert_eq!(vec!["http://rust-lang.org/"], matching(None, Some("2018-04-13"), Seen::First));
+    }
+    #[test]
+    fn test_since_last_seen() {
+        assert_eq!(
+            vec!["http://rust-lang.org/", "https://rust-lang.org/%0A", "http://www.rust-lang.org/%E2%80%A2"],
+            matching(Some("2024-02"), None, Seen::Last),
+        );
+    }
+    #[test]
+    fn test_range() {
+        // both ends have to hold, nothing from 2020 or 2024 gets in
+        assert_eq!(
+            vec!["http://rust-lang.org/", "http://www.rust-lang.org/%E2%80%99"],
+            matching(Some("2023"), Some("2023"), Seen::Any),
+        );
+        assert_eq!(vec!["http://www.rust-lang.org/%22"], matching(Some("2020-04-16"), Some("2020-04-16"), Seen::First));
+        assert!(matching(Some("2021"), Some("2021"), Seen::First).is_empty());
+    }
+    #[test]
+    fn test_undated() {
+        let row = TimemapRow {
+            original: "http://rust-lang.org/".to_owned(),
+            mime_type: "text/html".to_owned(),
+            from: None,
+            to: None,
+            group_count: 1,
+            uniq_count: 1,
+        };
+        assert!(DateFilter::default().matches(&row));
+        assert!(!DateFilter::new(Some(parse_period("2020").unwrap()), None, Seen::Any).matches(&row));
+    }
+}
@@ -1,4 +1,9 @@
+mod classify;
 mod client;
+mod filter;
+mod output;
+mod probe;
+mod query;
 use anyhow::Result;
 use clap::Parser;
 
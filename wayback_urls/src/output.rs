@@ -0,0 +1,375 @@
+// how urls get printed: bare (or the verbose table) as always, or in a format
+// other tools can read
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use reqwest::Url;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use wayback_api::timemap::TimemapRow;
+
+use crate::probe::{status_text, Probe};
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// urls, one per line, or a table with --verbose.
+    #[default]
+    Plain,
+    Json,
+    Jsonl,
+    Csv,
+    Tsv,
+}
+
+/// a row and the domain it turned up for, and what the url does now with --probe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Found {
+    pub domain: String,
+    pub row: TimemapRow,
+    pub probe: Option<Probe>,
+}
+
+impl Found {
+    pub fn new(domain: &str, row: TimemapRow) -> Self {
+        Found { domain: domain.to_owned(), row, probe: None }
+    }
+    // where the url redirects to now, if anywhere
+    fn redirect(&self) -> Option<&str> {
+        let target = self.probe.as_ref()?.target.as_deref()?;
+        (target != self.row.original).then_some(target)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Field {
+    /// the domain it was found for.
+    Domain,
+    Original,
+    Mimetype,
+    /// first capture.
+    From,
+    /// last capture.
+    To,
+    /// how many captures there are.
+    Groupcount,
+    /// how many of those differ in content.
+    Uniqcount,
+    /// status now, with --probe.
+    Status,
+    /// where it ends up now, after redirects.
+    Target,
+    /// content-length now.
+    Length,
+}
+
+impl Field {
+    fn name(&self) -> &'static str {
+        match self {
+            Field::Domain => "domain",
+            Field::Original => "original",
+            Field::Mimetype => "mimetype",
+            Field::From => "from",
+            Field::To => "to",
+            Field::Groupcount => "groupcount",
+            Field::Uniqcount => "uniqcount",
+            Field::Status => "status",
+            Field::Target => "target",
+            Field::Length => "length",
+        }
+    }
+    fn json(&self, found: &Found) -> Value {
+        let (row, probe) = (&found.row, found.probe.as_ref());
+        let date = |d: Option<NaiveDateTime>| d.map(|d| Value::from(d.format(TIME_FORMAT).to_string())).unwrap_or(Value::Null);
+        match self {
+            Field::Domain => Value::from(found.domain.as_str()),
+            Field::Original => Value::from(row.original.as_str()),
+            Field::Mimetype => Value::from(row.mime_type.as_str()),
+            Field::From => date(row.from),
+            Field::To => date(row.to),
+            Field::Groupcount => Value::from(row.group_count),
+            Field::Uniqcount => Value::from(row.uniq_count),
+            Field::Status => Value::from(probe.and_then(|p| p.status)),
+            Field::Target => Value::from(probe.and_then(|p| p.target.clone())),
+            Field::Length => Value::from(probe.and_then(|p| p.length)),
+        }
+    }
+    fn text(&self, found: &Found) -> String {
+        match self.json(found) {
+            Value::String(s) => s,
+            Value::Null => String::new(),
+            v => v.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Sort {
+    /// first capture, oldest first.
+    From,
+    /// last capture, oldest first.
+    To,
+    /// most captured first.
+    Count,
+    Url,
+}
+
+pub fn sort_rows(rows: &mut [Found], sort: Sort) {
+    match sort {
+        Sort::From => rows.sort_by_key(|f| f.row.from),
+        Sort::To => rows.sort_by_key(|f| f.row.to),
+        Sort::Count => rows.sort_by_key(|f| std::cmp::Reverse(f.row.group_count)),
+        Sort::Url => rows.sort_by(|a, b| a.row.original.cmp(&b.row.original)),
+    }
+}
+
+/// http and https, letter case in the host, default ports, trailing slashes,
+/// fragments and the order of query parameters don't make a different url.
+pub fn normalize_url(url: &str) -> String {
+    let Ok(parsed) = Url::parse(url) else {
+        return url.trim_end_matches('/').to_lowercase();
+    };
+    let mut query = parsed.query().map(|q| q.split('&').collect::<Vec<_>>()).unwrap_or_default();
+    query.sort();
+    format!(
+        "{}{}{}{}{}",
+        parsed.host_str().unwrap_or(""),
+        parsed.port().map(|p| format!(":{}", p)).unwrap_or_default(),
+        parsed.path().trim_end_matches('/'),
+        if query.is_empty() { "" } else { "?" },
+        query.join("&"),
+    )
+}
+
+/// one row per normalized url (whichever domain it was found for), in the order
+/// they first turn up. duplicates widen the first and last capture and add to
+/// the counts.
+pub fn dedup_rows(rows: Vec<Found>) -> Vec<Found> {
+    let mut out: Vec<Found> = Vec::new();
+    let mut seen = HashMap::new();
+    for found in rows {
+        let key = normalize_url(&found.row.original);
+        let Some(&i) = seen.get(&key) else {
+            seen.insert(key, out.len());
+            out.push(found);
+            continue;
+        };
+        let (kept, row) = (&mut out[i].row, found.row);
+        kept.from = [kept.from, row.from].into_iter().flatten().min();
+        kept.to = kept.to.max(row.to);
+        kept.group_count += row.group_count;
+        kept.uniq_count += row.uniq_count;
+    }
+    out
+}
+
+/// writes every row in format, with fields picking the columns. plain has its
+/// own, only going by whether domain and status are among them. everything but
+/// json goes out as the rows come in.
+pub fn write_rows(
+    out: &mut dyn Write,
+    format: Format,
+    fields: &[Field],
+    verbose: bool,
+    rows: impl Iterator<Item = Found>,
+) -> Result<()> {
+    let object = |found: &Found| fields.iter().map(|f| (f.name().to_owned(), f.json(found))).collect::<Map<_, _>>();
+    match format {
+        Format::Plain => {
+            let tagged = fields.contains(&Field::Domain);
+            let probed = fields.contains(&Field::Status);
+            if verbose {
+                if tagged {
+                    write!(out, "{0: <20} | ", "Domain:")?;
+                }
+                if probed {
+                    write!(out, "{0: <7} | {1: <10} | ", "Status:", "Length:")?;
+                }
+                writeln!(
+                    out,
+                    "{0: <20} | {1: <20} | {2: <11} | {3: <8} | {4: <25} | URL:",
+                    "From:", "To:", "Duplicates:", "Uniques:", "MIME Type:"
+                )?;
+            }
+            for found in rows {
+                let Found { domain, row, probe } = &found;
+                if tagged {
+                    write!(out, "{}", if verbose { format!("{: <20} | ", domain) } else { format!("{}\t", domain) })?;
+                }
+                if probed {
+                    let probe = probe.clone().unwrap_or_default();
+                    if verbose {
+                        let length = probe.length.map(|l| l.to_string()).unwrap_or_default();
+                        write!(out, "{: <7} | {: <10} | ", status_text(&probe), length)?;
+                    } else {
+                        write!(out, "{}\t", status_text(&probe))?;
+                    }
+                }
+                let redirect = if probed { found.redirect().map(|t| format!(" -> {}", t)).unwrap_or_default() } else { String::new() };
+                if verbose {
+                    let date = |d: Option<NaiveDateTime>| d.map(|d| d.to_string()).unwrap_or_default();
+                    writeln!(
+                        out,
+                        "{0: <20} | {1: <20} | {2: <11} | {3: <8} | {4: <25} | {5: }{6: }",
+                        date(row.from), date(row.to), row.group_count, row.uniq_count, row.mime_type, row.original, redirect,
+                    )?;
+                } else {
+                    writeln!(out, "{}{}", row.original, redirect)?;
+                }
+            }
+        },
+        Format::Json => {
+            let rows = rows.map(|row| Value::Object(object(&row))).collect::<Vec<_>>();
+            writeln!(out, "{}", serde_json::to_string_pretty(&rows)?)?;
+        },
+        Format::Jsonl => {
+            for row in rows {
+                writeln!(out, "{}", Value::Object(object(&row)))?;
+            }
+        },
+        Format::Csv | Format::Tsv => {
+            let (sep, escape): (&str, fn(&str) -> String) = if format == Format::Csv { (",", csv_field) } else { ("\t", tsv_field) };
+            writeln!(out, "{}", fields.iter().map(|f| f.name()).collect::<Vec<_>>().join(sep))?;
+            for row in rows {
+                writeln!(out, "{}", fields.iter().map(|f| escape(&f.text(&row))).collect::<Vec<_>>().join(sep))?;
+            }
+        },
+    }
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+// tsv has no quoting, so anything that would split a cell goes
+fn tsv_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayback_api::date::parse_timestamp;
+    use wayback_api::table::Page;
+
+    const ALL: [Field; 6] = [Field::Original, Field::Mimetype, Field::From, Field::To, Field::Groupcount, Field::Uniqcount];
+
+    fn fixture() -> Vec<Found> {
+        let page = Page::parse(&std::fs::read_to_string("wayback_response.json").unwrap()).unwrap();
+        page.parse_rows().unwrap().into_iter().map(|row| Found::new("rust-lang.org", row)).collect()
+    }
+    fn row(original: &str, from: &str, to: &str, count: u64) -> Found {
+        let row = TimemapRow {
+            original: original.to_owned(),
+            mime_type: "text/html".to_owned(),
+            from: parse_timestamp(from).ok(),
+            to: parse_timestamp(to).ok(),
+            group_count: count,
+            uniq_count: 1,
+        };
+        Found::new("rust-lang.org", row)
+    }
+    fn render(format: Format, fields: &[Field], rows: Vec<Found>) -> String {
+        render_verbose(format, fields, false, rows)
+    }
+    fn render_verbose(format: Format, fields: &[Field], verbose: bool, rows: Vec<Found>) -> String {
+        let mut out = Vec::new();
+        write_rows(&mut out, format, fields, verbose, rows.into_iter()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_csv_tsv() {
+        let csv = render(Format::Csv, &ALL, fixture());
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(10, lines.len());
+        assert_eq!("original,mimetype,from,to,groupcount,uniqcount", lines[0]);
+        assert_eq!("http://rust-lang.org/,text/html,2011-09-04T06:24:05,2024-05-12T09:23:35,7645,593", lines[1]);
+        assert_eq!("\"https://rust-lang.org/%22,\",unk,2024-01-06T14:50:49,2024-01-06T14:50:49,1,1", lines[3]);
+        let tsv = render(Format::Tsv, &[Field::Groupcount, Field::Original], vec![row("http://a.com/\tb", "2020", "-", 3)]);
+        assert_eq!("groupcount\toriginal\n3\thttp://a.com/ b\n", tsv);
+    }
+    #[test]
+    fn test_json() {
+        let json: Value = serde_json::from_str(&render(Format::Json, &ALL, fixture())).unwrap();
+        assert_eq!(9, json.as_array().unwrap().len());
+        assert_eq!("http://rust-lang.org/", json[0]["original"]);
+        assert_eq!(7645, json[0]["groupcount"]);
+        assert_eq!("2011-09-04T06:24:05", json[0]["from"]);
+        let jsonl = render(Format::Jsonl, &[Field::Original, Field::To], vec![row("http://a.com/", "2020", "-", 1)]);
+        assert_eq!("{\"original\":\"http://a.com/\",\"to\":null}\n", jsonl);
+    }
+    #[test]
+    fn test_plain() {
+        assert_eq!("http://a.com/\nhttp://b.com/\n", render(Format::Plain, &ALL, vec![row("http://a.com/", "2020", "2021", 1), row("http://b.com/", "2020", "2021", 1)]));
+    }
+    #[test]
+    fn test_domain() {
+        let mut rows = vec![row("http://a.com/", "2020", "2021", 1), row("http://b.a.com/", "2020", "2021", 1)];
+        rows[1].domain = "b.a.com".to_owned();
+        assert_eq!("rust-lang.org\thttp://a.com/\nb.a.com\thttp://b.a.com/\n", render(Format::Plain, &[Field::Domain], rows.clone()));
+        assert_eq!("domain,original\nrust-lang.org,http://a.com/\nb.a.com,http://b.a.com/\n", render(Format::Csv, &[Field::Domain, Field::Original], rows.clone()));
+        let table = render_verbose(Format::Plain, &[Field::Domain], true, rows);
+        assert!(table.starts_with("Domain:              | From:"));
+        assert!(table.contains("\nb.a.com              | 2020-01-01 00:00:00  | 2021-01-01 00:00:00  | 1"));
+    }
+    #[test]
+    fn test_probed() {
+        let mut rows = vec![row("http://a.com/", "2020", "2021", 1), row("http://a.com/old", "2020", "2021", 1), row("http://a.com/x", "2020", "2021", 1)];
+        rows[0].probe = Some(Probe { status: Some(200), target: Some("http://a.com/".to_owned()), length: Some(5) });
+        rows[1].probe = Some(Probe { status: Some(200), target: Some("https://a.com/new".to_owned()), length: None });
+        rows[2].probe = Some(Probe::default());
+        let fields = [Field::Original, Field::Status, Field::Target, Field::Length];
+        assert_eq!("200\thttp://a.com/\n200\thttp://a.com/old -> https://a.com/new\n-\thttp://a.com/x\n", render(Format::Plain, &fields, rows.clone()));
+        let csv = render(Format::Csv, &fields, rows.clone());
+        assert_eq!("original,status,target,length\nhttp://a.com/,200,http://a.com/,5\n", &csv[..csv.find("http://a.com/old").unwrap()]);
+        assert!(csv.ends_with("http://a.com/x,,,\n"));
+        let json: Value = serde_json::from_str(&render(Format::Json, &fields, rows.clone())).unwrap();
+        assert_eq!(Value::Null, json[2]["status"]);
+        let table = render_verbose(Format::Plain, &fields, true, rows);
+        assert!(table.starts_with("Status: | Length:    | From:"));
+        assert!(table.contains("\n200     | 5          | 2020-01-01 00:00:00"));
+    }
+    #[test]
+    fn test_sort() {
+        let mut rows = fixture();
+        sort_rows(&mut rows, Sort::Count);
+        assert_eq!("http://rust-lang.org/", rows[0].row.original);
+        sort_rows(&mut rows, Sort::From);
+        assert_eq!("http://rust-lang.org/", rows[0].row.original);
+        assert_eq!("http://www.rust-lang.org/%E2%80%A2", rows[8].row.original);
+        sort_rows(&mut rows, Sort::To);
+        assert_eq!("http://rust-lang.org/", rows[8].row.original);
+        sort_rows(&mut rows, Sort::Url);
+        assert_eq!("http://rust-lang.org/", rows[0].row.original);
+        assert!(rows.windows(2).all(|w| w[0].row.original <= w[1].row.original));
+    }
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!("rust-lang.org", normalize_url("http://rust-lang.org/"));
+        assert_eq!("rust-lang.org", normalize_url("https://Rust-Lang.org:443"));
+        assert_eq!("rust-lang.org:8080/learn?a=1&b=2", normalize_url("http://rust-lang.org:8080/learn/?b=2&a=1#top"));
+        assert_ne!(normalize_url("http://rust-lang.org/"), normalize_url("http://www.rust-lang.org/"));
+    }
+    #[test]
+    fn test_dedup() {
+        let rows = vec![
+            row("http://rust-lang.org/", "2015", "2016", 2),
+            row("http://www.rust-lang.org/", "2015", "2015", 1),
+            row("https://rust-lang.org", "2012", "2020", 3),
+        ];
+        let deduped = dedup_rows(rows);
+        assert_eq!(2, deduped.len());
+        assert_eq!("http://rust-lang.org/", deduped[0].row.original);
+        assert_eq!(parse_timestamp("2012").ok(), deduped[0].row.from);
+        assert_eq!(parse_timestamp("2020").ok(), deduped[0].row.to);
+        assert_eq!(5, deduped[0].row.group_count);
+        assert_eq!(2, deduped[0].row.uniq_count);
+    }
+}
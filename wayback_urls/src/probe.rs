@@ -0,0 +1,164 @@
+// --probe, whether the archived urls are still there: HEAD (or GET, for servers
+// that won't do HEAD) each one and see where it ends up
+use anyhow::Result;
+use reqwest::header::CONTENT_LENGTH;
+use serde_derive::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use wayback_api::transport::USER_AGENT;
+
+use crate::output::Found;
+
+// redirects followed before giving up on a url
+const MAX_REDIRECTS: usize = 10;
+
+/// what a url does now.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Probe {
+    /// none when there was no answer at all.
+    pub status: Option<u16>,
+    /// where it ended up after redirects.
+    pub target: Option<String>,
+    /// from content-length, when there is one.
+    pub length: Option<u64>,
+}
+
+impl Probe {
+    /// answered, and not with an error.
+    pub fn is_alive(&self) -> bool {
+        self.status.is_some_and(|status| status < 400)
+    }
+}
+
+pub struct Prober {
+    client: reqwest::blocking::Client,
+    concurrency: usize,
+}
+
+impl Prober {
+    /// timeout is for each request, start to finish.
+    pub fn new(timeout: Duration, concurrency: usize) -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::limited(MAX_REDIRECTS))
+            .build()?;
+        Ok(Prober { client, concurrency: concurrency.max(1) })
+    }
+    /// HEAD, then GET if HEAD fails or gets an error back. a timeout isn't
+    /// worth waiting on twice.
+    pub fn probe(&self, url: &str) -> Probe {
+        let response = match self.client.head(url).send() {
+            Ok(response) if response.status().as_u16() < 400 => Ok(response),
+            Err(e) if e.is_timeout() => Err(e),
+            _ => self.client.get(url).send(),
+        };
+        let Ok(response) = response else {
+            return Probe::default();
+        };
+        let length = response.headers().get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse().ok());
+        // only the headers are wanted, dropping it leaves the body unread
+        Probe { status: Some(response.status().as_u16()), target: Some(response.url().to_string()), length }
+    }
+    /// probes every row a few at a time, keeping them in order.
+    pub fn probe_all(&self, rows: Vec<Found>) -> Vec<Found> {
+        let next = AtomicUsize::new(0);
+        let probes = Mutex::new(vec![None; rows.len()]);
+        thread::scope(|s| {
+            for _ in 0..self.concurrency.min(rows.len()) {
+                s.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(found) = rows.get(i) else { break };
+                        let probe = self.probe(&found.row.original);
+                        probes.lock().unwrap()[i] = Some(probe);
+                    }
+                });
+            }
+        });
+        rows.into_iter()
+            .zip(probes.into_inner().unwrap())
+            .map(|(found, probe)| Found { probe, ..found })
+            .collect()
+    }
+}
+
+/// a status for printing, - for no answer.
+pub fn status_text(probe: &Probe) -> String {
+    probe.status.map(|s| s.to_string()).unwrap_or("-".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayback_api::test_server::{Response, TestServer};
+    use wayback_api::timemap::TimemapRow;
+
+    fn server() -> TestServer {
+        TestServer::start_with_method(|method, path| match (method, path) {
+            (_, "/ok") => Response::ok(b"hello"),
+            (_, "/moved") => Response::status(301).header("Location", "/ok"),
+            ("HEAD", "/no-head") => Response::status(405),
+            (_, "/no-head") => Response::ok(b"got it"),
+            _ => Response::status(404),
+        })
+    }
+    fn prober() -> Prober {
+        Prober::new(Duration::from_secs(5), 4).unwrap()
+    }
+
+    #[test]
+    fn test_probe() {
+        let server = server();
+        let url = |path: &str| format!("{}{}", server.base, path);
+        let ok = prober().probe(&url("/ok"));
+        assert_eq!(Probe { status: Some(200), target: Some(url("/ok")), length: Some(5) }, ok);
+        assert_eq!(1, server.hits("/ok"));
+        let moved = prober().probe(&url("/moved"));
+        assert_eq!(Some(200), moved.status);
+        assert_eq!(Some(url("/ok")), moved.target);
+        // HEAD isn't allowed, GET is
+        let no_head = prober().probe(&url("/no-head"));
+        assert_eq!(Some(200), no_head.status);
+        assert_eq!(Some(6), no_head.length);
+        assert_eq!(2, server.hits("/no-head"));
+        let gone = prober().probe(&url("/gone"));
+        assert_eq!(Some(404), gone.status);
+        assert!(!gone.is_alive());
+        assert!(ok.is_alive());
+    }
+    #[test]
+    fn test_probe_nothing_there() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let probe = prober().probe(&format!("http://{}/", addr));
+        assert_eq!(Probe::default(), probe);
+        assert_eq!("-", status_text(&probe));
+        assert!(!probe.is_alive());
+    }
+    #[test]
+    fn test_probe_all() {
+        let server = server();
+        let paths = ["/ok", "/gone", "/moved", "/no-head", "/also-gone"];
+        let rows = paths.iter()
+            .map(|path| {
+                let row = TimemapRow {
+                    original: format!("{}{}", server.base, path),
+                    mime_type: "text/html".to_owned(),
+                    from: None,
+                    to: None,
+                    group_count: 1,
+                    uniq_count: 1,
+                };
+                Found::new("127.0.0.1", row)
+            })
+            .collect();
+        let probed = prober().probe_all(rows);
+        let statuses = probed.iter().map(|f| f.probe.as_ref().unwrap().status.unwrap()).collect::<Vec<_>>();
+        assert_eq!(vec![200, 404, 200, 200, 404], statuses);
+        assert_eq!(format!("{}/moved", server.base), probed[2].row.original);
+    }
+}
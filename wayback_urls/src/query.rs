@@ -0,0 +1,175 @@
+// the timemap request: what to match, what to filter out and how to collapse
+// captures, from the flags
+use anyhow::Result;
+use clap::ValueEnum;
+use reqwest::Url;
+
+const TIMEMAP_URL: &str = "https://web.archive.org/web/timemap/json";
+const TIMEMAP_FIELDS: &str = "original,mimetype,timestamp,endtimestamp,groupcount,uniqcount";
+// what there was before any of this could be changed
+const DEFAULT_EXCLUDE_STATUS: &[&str] = &["4..", "5.."];
+pub const DEFAULT_COLLAPSE: &str = "urlkey";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum MatchType {
+    /// just the url.
+    Exact,
+    /// everything under it.
+    #[default]
+    Prefix,
+    /// everything on the host.
+    Host,
+    /// everything on the host and its subdomains.
+    Domain,
+}
+
+impl MatchType {
+    fn name(&self) -> &'static str {
+        match self {
+            MatchType::Exact => "exact",
+            MatchType::Prefix => "prefix",
+            MatchType::Host => "host",
+            MatchType::Domain => "domain",
+        }
+    }
+}
+
+/// a status code, or a pattern like 3xx.
+pub fn parse_status(status: &str) -> Result<String, String> {
+    let ok = status.len() == 3 && status.chars().all(|c| c.is_ascii_digit() || matches!(c, 'x' | 'X' | '.'));
+    if !ok {
+        return Err(format!("{} isn't a status code like 200 or 3xx", status));
+    }
+    Ok(status.replace(['x', 'X'], "."))
+}
+
+/// a mime type, with * for anything, as a regex.
+pub fn parse_mime(mime: &str) -> Result<String, String> {
+    if mime.is_empty() {
+        return Err("empty mime type".to_owned());
+    }
+    let mut out = String::new();
+    for c in mime.chars() {
+        match c {
+            '*' => out.push_str(".*"),
+            '.' | '+' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '^' | '$' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            },
+            _ => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub match_type: MatchType,
+    /// only these statuses, as regexes.
+    pub status: Vec<String>,
+    /// none of these statuses. None for the default, which is no 4xx or 5xx
+    /// unless there's a status to keep.
+    pub exclude_status: Option<Vec<String>>,
+    pub mime: Vec<String>,
+    pub exclude_mime: Vec<String>,
+    /// one capture per whatever these fields have in common, like urlkey or
+    /// timestamp:6.
+    pub collapse: Vec<String>,
+}
+
+impl Query {
+    fn filters(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        // keeping any of several is one filter, leaving out several is a filter each
+        let any = |values: &[String]| match values {
+            [one] => one.clone(),
+            _ => format!("({})", values.join("|")),
+        };
+        if !self.status.is_empty() {
+            out.push(format!("statuscode:{}", any(&self.status)));
+        }
+        let default = DEFAULT_EXCLUDE_STATUS.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let exclude_status = match &self.exclude_status {
+            Some(exclude) => exclude,
+            None if self.status.is_empty() => &default,
+            None => &Vec::new(),
+        };
+        out.extend(exclude_status.iter().map(|s| format!("!statuscode:{}", s)));
+        if !self.mime.is_empty() {
+            out.push(format!("mimetype:{}", any(&self.mime)));
+        }
+        out.extend(self.exclude_mime.iter().map(|m| format!("!mimetype:{}", m)));
+        out
+    }
+    /// the timemap url for domain, encoded. nonce keeps anything in between
+    /// from caching it.
+    pub fn url(&self, domain: &str, nonce: u128) -> Result<String> {
+        let mut url = Url::parse(TIMEMAP_URL)?;
+        {
+            let mut pairs = url.query_pairs_mut();
+            pairs.append_pair("matchType", self.match_type.name());
+            for collapse in &self.collapse {
+                pairs.append_pair("collapse", collapse);
+            }
+            pairs.append_pair("output", "json").append_pair("fl", TIMEMAP_FIELDS);
+            for filter in self.filters() {
+                pairs.append_pair("filter", &filter);
+            }
+            pairs.append_pair("_", &nonce.to_string()).append_pair("url", domain);
+        }
+        Ok(url.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query() -> Query {
+        Query { collapse: vec![DEFAULT_COLLAPSE.to_owned()], ..Default::default() }
+    }
+
+    #[test]
+    fn test_default_url() {
+        let url = query().url("rust-lang.org", 1).unwrap();
+        assert_eq!(
+            "https://web.archive.org/web/timemap/json?matchType=prefix&collapse=urlkey&output=json\
+             &fl=original%2Cmimetype%2Ctimestamp%2Cendtimestamp%2Cgroupcount%2Cuniqcount\
+             &filter=%21statuscode%3A4..&filter=%21statuscode%3A5..&_=1&url=rust-lang.org",
+            url,
+        );
+    }
+    #[test]
+    fn test_filters() {
+        let query = Query {
+            match_type: MatchType::Domain,
+            status: vec!["200".to_owned(), "3..".to_owned()],
+            mime: vec![parse_mime("text/html").unwrap()],
+            exclude_mime: vec![parse_mime("image/*").unwrap(), parse_mime("application/xhtml+xml").unwrap()],
+            ..Default::default()
+        };
+        // keeping statuses drops the default exclusions
+        assert_eq!(
+            vec!["statuscode:(200|3..)", "mimetype:text/html", "!mimetype:image/.*", r"!mimetype:application/xhtml\+xml"],
+            query.filters(),
+        );
+        let url = query.url("rust-lang.org", 1).unwrap();
+        assert!(url.contains("?matchType=domain&output=json&"));
+        assert!(!url.contains("collapse"));
+        let everything = Query { exclude_status: Some(Vec::new()), ..Default::default() };
+        assert!(everything.filters().is_empty());
+    }
+    #[test]
+    fn test_url_encodes_domain() {
+        let url = query().url("rust-lang.org/learn?x=1&url=evil.com#top", 1).unwrap();
+        assert!(url.ends_with("&url=rust-lang.org%2Flearn%3Fx%3D1%26url%3Devil.com%23top"));
+    }
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok("3..".to_owned()), parse_status("3xx"));
+        assert_eq!(Ok("404".to_owned()), parse_status("404"));
+        assert!(parse_status("20").is_err());
+        assert!(parse_status("2[0]").is_err());
+        assert!(parse_mime("").is_err());
+    }
+}